@@ -1,71 +1,94 @@
-use std::env;
-use std::path::PathBuf;
-use bindgen::Builder;
-use bindgen::CargoCallbacks;
-// use cc::Build;
+// Only the `rpi` feature needs a build step: generating the bindings to the
+// rpi-rgb-led-matrix C library and linking it.
+//
+// The library is looked up, in this order, in:
+//  - RGB_MATRIX_LIB_DIR / RGB_MATRIX_INCLUDE_DIR
+//  - RGB_MATRIX_DIR, the root of a rpi-rgb-led-matrix checkout (lib/ and include/)
+//  - pkg-config, under the name rgbmatrix
+//  - the 3rdparty/rpi-rgb-led-matrix submodule
 
+#[cfg(feature = "rpi")]
+mod rgbmatrix {
+    use std::env;
+    use std::path::PathBuf;
+    use bindgen::Builder;
+    use bindgen::CargoCallbacks;
 
-fn main() {
+    const SUBMODULE_DIR: &str = "3rdparty/rpi-rgb-led-matrix";
+
+    // returns the include directories, the link directives are printed
+    fn find_library() -> Vec<PathBuf> {
+        for var in ["RGB_MATRIX_LIB_DIR", "RGB_MATRIX_INCLUDE_DIR", "RGB_MATRIX_DIR"] {
+            println!("cargo:rerun-if-env-changed={var}");
+        }
 
-       let lib_name = "rgbmatrix";
-    println!("cargo:rustc-link-search=native=/home/bjorn/WORK/led_matrix_rust/3rdparty/rpi-rgb-led-matrix/lib");
-    println!("cargo:rustc-link-lib=dylib=rgbmatrix");
-    // Build::new()
-    //  .cpp(true)  // Compile a C++ library
-    //  .flag("-std=c++11")
-    //  .include("./3rdparty/rpi-rgb-led-matrix/include")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/bdf-font.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/led-matrix-c.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/content-streamer.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/multiplex-mappers.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/framebuffer.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/options-initialize.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/gpio.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/pixel-mapper.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/graphics.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/thread.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/led-matrix.cc")
-    //  .file("./3rdparty/rpi-rgb-led-matrix/lib/hardware-mapping.c")
-    //  .compile(lib_name);
+        let lib_dir = env::var_os("RGB_MATRIX_LIB_DIR").map(PathBuf::from);
+        let include_dir = env::var_os("RGB_MATRIX_INCLUDE_DIR").map(PathBuf::from);
+        let root_dir = env::var_os("RGB_MATRIX_DIR").map(PathBuf::from);
 
+        if lib_dir.is_some() || include_dir.is_some() || root_dir.is_some() {
+            let root = root_dir.unwrap_or_else(|| PathBuf::from(SUBMODULE_DIR));
+            let lib_dir = lib_dir.unwrap_or_else(|| root.join("lib"));
+            let include_dir = include_dir.unwrap_or_else(|| root.join("include"));
+            println!("cargo:rustc-link-search=native={}", lib_dir.display());
+            println!("cargo:rustc-link-lib=dylib=rgbmatrix");
+            return vec![include_dir];
+        }
 
+        if let Ok(library) = pkg_config::Config::new().probe("rgbmatrix") {
+            return library.include_paths;
+        }
 
+        let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join(SUBMODULE_DIR);
+        println!("cargo:rustc-link-search=native={}", root.join("lib").display());
+        println!("cargo:rustc-link-lib=dylib=rgbmatrix");
+        vec![root.join("include")]
+    }
 
+    pub fn generate_bindings() {
+        let include_dirs = find_library();
 
-    let bindings = Builder::default()
-    .header("src/wrapper.h")
-        // Add functions to the allowlist
-    .allowlist_function("led_matrix_create_from_options")
-    .allowlist_function("led_matrix_create_from_options_and_rt_options")
-    .allowlist_function("led_matrix_print_flags")
-    .allowlist_function("led_matrix_create")
-    .allowlist_function("led_matrix_delete")
-    .allowlist_function("led_matrix_get_canvas")
-    .allowlist_function("led_canvas_get_size")
-    .allowlist_function("led_canvas_set_pixel")
-    .allowlist_function("led_canvas_set_pixels")
-    .allowlist_function("led_canvas_clear")
-    .allowlist_function("led_canvas_fill")
-    .allowlist_function("draw_text")
-    .allowlist_function("load_font")
-    .allowlist_function("led_matrix_create_offscreen_canvas")
-    .allowlist_function("led_matrix_swap_on_vsync")
-    .allowlist_function("led_matrix_get_brightness")
-    .allowlist_function("led_matrix_set_brightness")
-    // Add structs to the allowlist
-    .allowlist_type("RGBLedMatrix")
-    .allowlist_type("LedCanvas")
-    .allowlist_type("LedFont")
-    .allowlist_type("RGBLedMatrixOptions")
-    .allowlist_type("RGBLedRuntimeOptions")
-    .allowlist_type("Color")
-    .parse_callbacks(Box::new(CargoCallbacks))
-    .generate()
-    .expect("Failed to generate bindings");
+        let bindings = Builder::default()
+        .header("src/wrapper.h")
+        .clang_args(include_dirs.iter().map(|dir| format!("-I{}", dir.display())))
+            // Add functions to the allowlist
+        .allowlist_function("led_matrix_create_from_options")
+        .allowlist_function("led_matrix_create_from_options_and_rt_options")
+        .allowlist_function("led_matrix_print_flags")
+        .allowlist_function("led_matrix_create")
+        .allowlist_function("led_matrix_delete")
+        .allowlist_function("led_matrix_get_canvas")
+        .allowlist_function("led_canvas_get_size")
+        .allowlist_function("led_canvas_set_pixel")
+        .allowlist_function("led_canvas_set_pixels")
+        .allowlist_function("led_canvas_clear")
+        .allowlist_function("led_canvas_fill")
+        .allowlist_function("draw_text")
+        .allowlist_function("load_font")
+        .allowlist_function("led_matrix_create_offscreen_canvas")
+        .allowlist_function("led_matrix_swap_on_vsync")
+        .allowlist_function("led_matrix_get_brightness")
+        .allowlist_function("led_matrix_set_brightness")
+        // Add structs to the allowlist
+        .allowlist_type("RGBLedMatrix")
+        .allowlist_type("LedCanvas")
+        .allowlist_type("LedFont")
+        .allowlist_type("RGBLedMatrixOptions")
+        .allowlist_type("RGBLedRuntimeOptions")
+        .allowlist_type("Color")
+        .parse_callbacks(Box::new(CargoCallbacks))
+        .generate()
+        .expect("Failed to generate bindings, set RGB_MATRIX_DIR to the rpi-rgb-led-matrix checkout");
 
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-    println!("{}", out_path.display());
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+        let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+        bindings
+            .write_to_file(out_path.join("bindings.rs"))
+            .expect("Couldn't write bindings!");
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "rpi")]
+    rgbmatrix::generate_bindings();
 }
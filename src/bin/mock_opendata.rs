@@ -0,0 +1,282 @@
+// Stand-in for transport.opendata.ch, to run the board without the real API
+// and without spending its quota. Serves /v1/connections, /v1/stationboard and
+// /v1/locations from the JSON files of a fixture directory, and can answer
+// late, never, with a 429, with broken JSON or with no result.
+//
+//   cargo run --bin mock_opendata -- --address=127.0.0.1:18080 --fixtures=fixtures/opendata --mode=ok
+//
+// then `api_url = "http://127.0.0.1:18080/v1/"` in [settings]. The mode can be
+// changed while it runs: curl "http://127.0.0.1:18080/mock?mode=429"
+
+use std::convert::Infallible;
+use std::env;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde_json::Value;
+
+// the first departure of a fixture is moved this far ahead of now
+const FIRST_DEPARTURE_IN: u64 = 120;
+// longer than any client timeout
+const TIMEOUT_SLEEP: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // the fixture
+    Ok,
+    // the fixture, after `delay`
+    Delay,
+    // no answer at all
+    Timeout,
+    // HTTP 429 Too Many Requests
+    RateLimited,
+    // the fixture cut in the middle
+    Malformed,
+    // a valid answer without any result
+    Empty,
+}
+
+pub fn parse_mode(name: &str) -> Option<Mode> {
+    match name {
+        "ok" => Some(Mode::Ok),
+        "delay" => Some(Mode::Delay),
+        "timeout" => Some(Mode::Timeout),
+        "429" => Some(Mode::RateLimited),
+        "malformed" => Some(Mode::Malformed),
+        "empty" => Some(Mode::Empty),
+        _ => None,
+    }
+}
+
+struct MockState {
+    mode: Mode,
+    delay: Duration,
+}
+
+pub struct Mock {
+    fixtures: PathBuf,
+    // keep the times of the fixtures instead of moving them to now
+    keep_times: bool,
+    // changed by /mock
+    state: Mutex<MockState>,
+}
+
+impl Mock {
+    pub fn new(fixtures: PathBuf, mode: Mode, delay: Duration) -> Mock {
+        Mock { fixtures, keep_times: false, state: Mutex::new(MockState { mode, delay }) }
+    }
+}
+
+fn reply(status: StatusCode, content_type: &str, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    if let Ok(value) = content_type.parse() {
+        response.headers_mut().insert(hyper::header::CONTENT_TYPE, value);
+    }
+    response
+}
+
+fn text(status: StatusCode, message: &str) -> Response<Body> {
+    reply(status, "text/plain; charset=utf-8", message.to_string())
+}
+
+fn query_value(request: &Request<Body>, key: &str) -> Option<String> {
+    request.uri().query().and_then(|query| {
+        query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+// the key holding the results of each endpoint
+fn results_key(endpoint: &str) -> &'static str {
+    match endpoint {
+        "connections" => "connections",
+        "stationboard" => "stationboard",
+        _ => "stations",
+    }
+}
+
+fn earliest_timestamp(value: &Value) -> Option<u64> {
+    match value {
+        Value::Object(map) => {
+            map.iter()
+                .filter_map(|(key, v)| match v {
+                    Value::Number(n) if key.ends_with("Timestamp") => { n.as_u64() }
+                    _ => { earliest_timestamp(v) }
+                })
+                .min()
+        }
+        Value::Array(items) => { items.iter().filter_map(earliest_timestamp).min() }
+        _ => { None }
+    }
+}
+
+fn shift_timestamps(value: &mut Value, shift: i64) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match v.as_u64() {
+                    Some(t) if key.ends_with("Timestamp") => { *v = Value::from(t as i64 + shift); }
+                    _ => { shift_timestamps(v, shift); }
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                shift_timestamps(item, shift);
+            }
+        }
+        _ => {}
+    }
+}
+
+// the fixture of `endpoint` with its first departure two minutes from now,
+// read on each request so it can be edited while the server runs
+async fn fixture(mock: &Mock, endpoint: &str) -> Result<String, Response<Body>> {
+    let path = mock.fixtures.join(format!("{endpoint}.json"));
+    let content = tokio::fs::read_to_string(&path).await
+        .map_err(|e| text(StatusCode::NOT_FOUND, &format!("{}: {e}", path.display())))?;
+    if mock.keep_times {
+        return Ok(content);
+    }
+    let mut json: Value = serde_json::from_str(&content)
+        .map_err(|e| text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{}: {e}", path.display())))?;
+    if let Some(earliest) = earliest_timestamp(&json) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        shift_timestamps(&mut json, (now + FIRST_DEPARTURE_IN) as i64 - earliest as i64);
+    }
+    Ok(json.to_string())
+}
+
+async fn handle(mock: &Mock, request: Request<Body>) -> Response<Body> {
+    let path = request.uri().path().to_string();
+    if path == "/mock" {
+        let mut state = mock.state.lock().unwrap();
+        if let Some(name) = query_value(&request, "mode") {
+            match parse_mode(&name) {
+                Some(mode) => { state.mode = mode; }
+                None => { return text(StatusCode::BAD_REQUEST, &format!("unknown mode {name}")); }
+            }
+        }
+        if let Some(seconds) = query_value(&request, "delay") {
+            match seconds.parse() {
+                Ok(e) => { state.delay = Duration::from_secs(e); }
+                Err(_) => { return text(StatusCode::BAD_REQUEST, &format!("invalid delay {seconds}")); }
+            }
+        }
+        println!("mode {:?}, delay {}s", state.mode, state.delay.as_secs());
+        return text(StatusCode::OK, &format!("mode {:?}, delay {}s", state.mode, state.delay.as_secs()));
+    }
+
+    let endpoint = match path.strip_prefix("/v1/") {
+        Some(e @ ("connections" | "stationboard" | "locations")) => { e.to_string() }
+        _ => { return text(StatusCode::NOT_FOUND, "not found"); }
+    };
+    let (mode, delay) = {
+        let state = mock.state.lock().unwrap();
+        (state.mode, state.delay)
+    };
+    println!("{mode:?} {}", request.uri());
+
+    match mode {
+        Mode::Timeout => {
+            tokio::time::sleep(TIMEOUT_SLEEP).await;
+            return text(StatusCode::GATEWAY_TIMEOUT, "timeout");
+        }
+        Mode::RateLimited => {
+            return reply(StatusCode::TOO_MANY_REQUESTS, "application/json", r#"{"errors":[{"message":"Rate limit exceeded"}]}"#);
+        }
+        Mode::Empty => {
+            return reply(StatusCode::OK, "application/json", format!(r#"{{"{}":[]}}"#, results_key(&endpoint)));
+        }
+        Mode::Delay => { tokio::time::sleep(delay).await; }
+        Mode::Ok | Mode::Malformed => {}
+    }
+
+    let content = match fixture(mock, &endpoint).await {
+        Ok(e) => { e }
+        Err(response) => { return response; }
+    };
+    if mode == Mode::Malformed {
+        let mut bytes = content.into_bytes();
+        bytes.truncate(bytes.len() / 2);
+        return reply(StatusCode::OK, "application/json", bytes);
+    }
+    reply(StatusCode::OK, "application/json", content)
+}
+
+fn parse_args() -> Result<(SocketAddr, Mock), String> {
+    let mut address = SocketAddr::from(([127, 0, 0, 1], 18080));
+    let mut mock = Mock::new(PathBuf::from("fixtures/opendata"), Mode::Ok, Duration::from_secs(5));
+    for arg in env::args().skip(1) {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => { (name.to_string(), value.to_string()) }
+            None => { (arg.clone(), String::new()) }
+        };
+        match name.as_str() {
+            "--address" => { address = value.parse().map_err(|e| format!("--address: {e}"))?; }
+            "--fixtures" => { mock.fixtures = PathBuf::from(value); }
+            "--keep-times" => { mock.keep_times = true; }
+            "--mode" => {
+                let mode = parse_mode(&value).ok_or(format!("--mode: unknown mode `{value}`"))?;
+                mock.state.get_mut().unwrap().mode = mode;
+            }
+            "--delay" => {
+                let seconds = value.parse().map_err(|e| format!("--delay: {e}"))?;
+                mock.state.get_mut().unwrap().delay = Duration::from_secs(seconds);
+            }
+            _ => { return Err(format!("unknown argument {arg}")); }
+        }
+    }
+    Ok((address, mock))
+}
+
+// listens on `address` (port 0 for any free port), the returned address is the one bound;
+// the requests are answered while the future runs
+pub fn bind(address: SocketAddr, mock: Mock) -> Result<(SocketAddr, impl Future<Output = Result<(), hyper::Error>>), String> {
+    let mock = Arc::new(mock);
+    let make_service = make_service_fn(move |_| {
+        let mock = mock.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let mock = mock.clone();
+                async move { Ok::<_, Infallible>(handle(&mock, request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&address)
+        .map_err(|e| format!("cannot listen on {address}: {e}"))?
+        .serve(make_service);
+    Ok((server.local_addr(), server))
+}
+
+#[tokio::main]
+async fn main() {
+    let (address, mock) = match parse_args() {
+        Ok(e) => { e }
+        Err(e) => {
+            println!("{e}");
+            println!("Usage: mock_opendata [--address=127.0.0.1:18080] [--fixtures=fixtures/opendata] [--keep-times]");
+            println!("                     [--mode=ok|delay|timeout|429|malformed|empty] [--delay=5]");
+            std::process::exit(2);
+        }
+    };
+    let fixtures = mock.fixtures.clone();
+    let (address, server) = match bind(address, mock) {
+        Ok(e) => { e }
+        Err(e) => {
+            println!("{e}");
+            std::process::exit(1);
+        }
+    };
+    println!("serving {} on http://{address}/v1/", fixtures.display());
+    if let Err(e) = server.await {
+        println!("server stopped: {e}");
+    }
+}
@@ -0,0 +1,38 @@
+// Time of the board. It is the system time, except when replaying captures:
+// the clock is then moved back to the time they were recorded and runs from there.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, SystemTime};
+use chrono::Local;
+
+// milliseconds added to the system time
+static OFFSET_MS: AtomicI64 = AtomicI64::new(0);
+
+pub fn now() -> SystemTime {
+    let offset = OFFSET_MS.load(Ordering::Relaxed);
+    let real = SystemTime::now();
+    if offset >= 0 {
+        real + Duration::from_millis(offset as u64)
+    } else {
+        real - Duration::from_millis(offset.unsigned_abs())
+    }
+}
+
+pub fn local_now() -> chrono::DateTime<Local> {
+    now().into()
+}
+
+// from now on the clock reads `time`
+pub fn start_at(time: SystemTime) {
+    let real = SystemTime::now();
+    let offset = match time.duration_since(real) {
+        Ok(ahead) => { ahead.as_millis() as i64 }
+        Err(e) => { -(e.duration().as_millis() as i64) }
+    };
+    OFFSET_MS.store(offset, Ordering::Relaxed);
+}
+
+// back to the system time
+pub fn reset() {
+    OFFSET_MS.store(0, Ordering::Relaxed);
+}
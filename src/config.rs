@@ -0,0 +1,823 @@
+// Configuration file: describes the dashboard pages, the connections shown on
+// each page and a few global settings. See config.toml for an example and
+// config.schema.json for the full schema.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::matrix::MatrixConfig;
+use crate::quota::ActiveHours;
+use crate::{FPS, REFRESH_INFERVAL, SECOND_NUM_WAIT};
+
+// the transport.opendata.ch connections endpoint does not return more than 16 results
+const MAX_LIMIT: u32 = 16;
+// the stationboard is filtered locally, more departures are needed
+const MAX_STATIONBOARD_LIMIT: u32 = 50;
+const MAX_FPS: u32 = 120;
+const MAX_PRIORITY: u32 = 10;
+// departures on the row of a line
+const MAX_GROUP_SIZE: u32 = 5;
+// via[] of the connections endpoint
+const MAX_VIA: usize = 5;
+// APNG frames are kept in memory until the end, about 36 MB for a 192x64 canvas
+const MAX_APNG_FRAMES: u64 = 1000;
+// transportations[] values known by transport.opendata.ch
+const TRANSPORTATIONS: [&str; 10] = [
+    "train", "tram", "ship", "bus", "cableway", "ice_tgv_rj", "ec_ic", "ir", "re_d", "s_sn_r",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    // panel geometry and timings, only used at startup
+    #[serde(default)]
+    pub matrix: MatrixConfig,
+    #[serde(default)]
+    pub web: WebConfig,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    // offline timetable, needed by the gtfs provider
+    pub gtfs: Option<GtfsConfig>,
+    // GTFS-RT feed overlaid on the gtfs departures
+    pub realtime: Option<RealtimeConfig>,
+    // saves or replays the API answers
+    pub capture: Option<CaptureConfig>,
+    #[serde(default)]
+    pub pages: Vec<PageConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    // shortest time between two requests for the same entry, in seconds;
+    // the entries using the API wait longer when the daily quota runs low
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+    #[serde(default = "default_fps")]
+    pub fps: u32,
+    // seconds an entry stays still before scrolling to the next one
+    #[serde(default = "default_second_num_wait")]
+    pub second_num_wait: u32,
+    // BDF font given to the led matrix library
+    #[serde(default = "default_font")]
+    pub font: String,
+    // files kept across restarts, e.g. the quota count
+    #[serde(default = "default_state_dir")]
+    pub state_dir: String,
+    // base of the transport.opendata.ch requests, e.g. a local mock_opendata server
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    // the led panels, through the rpi-rgb-led-matrix library
+    Rpi,
+    // desktop window
+    Sdl,
+    // frames written to disk, see [display.file]
+    File,
+    // preview drawn with colored characters in the terminal
+    Terminal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    // numbered PNG files in the `path` directory
+    Png,
+    Gif,
+    Apng,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FileOutputConfig {
+    #[serde(default = "default_file_format")]
+    pub format: FileFormat,
+    // directory for a PNG sequence, file for GIF and APNG
+    #[serde(default = "default_file_path")]
+    pub path: String,
+    // keep one frame out of `every`
+    #[serde(default = "default_every")]
+    pub every: u32,
+    // seconds after startup before the first frame is written
+    #[serde(default)]
+    pub start: u64,
+    // seconds recorded, the program exits once they are written
+    pub duration: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TerminalConfig {
+    // the terminal is refreshed less often than the panel, ssh sessions are slow
+    #[serde(default = "default_terminal_fps")]
+    pub fps: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DisplayConfig {
+    #[serde(default = "default_backend")]
+    pub backend: BackendKind,
+    // backends showing the same frames as `backend`
+    #[serde(default)]
+    pub mirror: Vec<BackendKind>,
+    // size of one led in the simulator window, in screen pixels
+    #[serde(default = "default_scale")]
+    pub scale: u32,
+    // SDL video driver, "dummy" or "offscreen" to run the simulator without a display
+    pub video_driver: Option<String>,
+    #[serde(default)]
+    pub file: FileOutputConfig,
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebConfig {
+    // phone remote and status API
+    #[serde(default = "default_web_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_web_address")]
+    pub address: String,
+    // directory served to the browser
+    #[serde(default = "default_web_assets")]
+    pub assets: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaConfig {
+    // transport.opendata.ch requests allowed per UTC day
+    #[serde(default = "default_daily_limit")]
+    pub daily_limit: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PageConfig {
+    #[serde(default)]
+    pub entries: Vec<EntryConfig>,
+    // seconds the page stays on screen, checked when an entry finishes scrolling
+    pub dwell: Option<u64>,
+    // number of entries scrolled before moving to the next page
+    pub cycles: Option<u32>,
+    // a sticky page is only left on request, never by the rotation
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    // transport.opendata.ch connections between `from` and `to`
+    Connections,
+    // transport.opendata.ch departures from the `from` stop, whatever their destination
+    Stationboard,
+    // scheduled departures from the [gtfs] timetable, toward `to` when it is set
+    Gtfs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JourneyFormat {
+    // a 2x2 grid of departures, "T4>S12" when a change is needed
+    Compact,
+    // each connection on several lines: legs, changes, arrival and travel time
+    Detailed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GtfsConfig {
+    // GTFS zip file
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RealtimeConfig {
+    // http(s) URL or file of a GTFS-RT protobuf feed
+    pub source: String,
+    // seconds between two reads of the feed
+    #[serde(default = "default_realtime_refresh")]
+    pub refresh_interval: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureMode {
+    // every API answer is saved in `dir`
+    Record,
+    // the answers of `dir` are served instead of asking the API, the clock starts at the first one
+    Replay,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub mode: CaptureMode,
+    #[serde(default = "default_capture_dir")]
+    pub dir: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EntryConfig {
+    pub label: String,
+    // where the departures come from
+    #[serde(default = "default_provider")]
+    pub provider: ProviderKind,
+    // used when `provider` fails or has no departures
+    pub fallback: Option<ProviderKind>,
+    pub from: String,
+    // not used by the stationboard
+    #[serde(default)]
+    pub to: String,
+    // connections passing by these stations
+    #[serde(default)]
+    pub via: Vec<String>,
+    // only these means of transport, e.g. ["tram", "bus"]; everything when empty
+    #[serde(default)]
+    pub transportations: Vec<String>,
+    // connections without any change
+    #[serde(default)]
+    pub direct: bool,
+    // connections from this day and time ("YYYY-MM-DD", "HH:MM") instead of now
+    pub date: Option<String>,
+    pub time: Option<String>,
+    // `date` and `time` are the arrival instead of the departure
+    #[serde(default)]
+    pub is_arrival_time: bool,
+    #[serde(default = "default_color")]
+    pub color: [u8; 3],
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+    // fields[] filters of the request, each provider has its own default
+    pub fields: Option<Vec<String>>,
+    // stationboard filters, an empty list keeps everything
+    #[serde(default)]
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    // kept when the headsign contains one of them
+    #[serde(default)]
+    pub destinations: Vec<String>,
+    // how the connections are drawn
+    #[serde(default = "default_journey")]
+    pub journey: JourneyFormat,
+    // one row per line and headsign with its next `group_size` departures,
+    // instead of the next departures in time order
+    #[serde(default)]
+    pub group_by_line: bool,
+    #[serde(default = "default_group_size")]
+    pub group_size: u32,
+    // share of the daily quota, an entry with priority 2 refreshes twice as often as one with 1
+    #[serde(default = "default_priority")]
+    pub priority: u32,
+    // local "HH:MM-HH:MM" ranges when the entry is refreshed, always when empty
+    #[serde(default)]
+    pub active_hours: Vec<String>,
+    // seconds to walk to the stop, the departures leaving sooner are hidden
+    // and the countdown of the next one is the time left before leaving
+    #[serde(default)]
+    pub walk_time: u64,
+    // seconds when running, the departures between run_time and walk_time stay visible
+    pub run_time: Option<u64>,
+}
+
+fn default_refresh_interval() -> u64 {
+    REFRESH_INFERVAL.as_secs()
+}
+
+fn default_fps() -> u32 {
+    FPS
+}
+
+fn default_second_num_wait() -> u32 {
+    SECOND_NUM_WAIT
+}
+
+fn default_font() -> String {
+    "fonts/5x7.bdf".to_string()
+}
+
+fn default_api_url() -> String {
+    "http://www.transport.opendata.ch/v1/".to_string()
+}
+
+fn default_state_dir() -> String {
+    "state".to_string()
+}
+
+fn default_daily_limit() -> u32 {
+    1000
+}
+
+fn default_priority() -> u32 {
+    1
+}
+
+fn default_group_size() -> u32 {
+    3
+}
+
+// the panels when they can be driven, otherwise a backend available in every build
+fn default_backend() -> BackendKind {
+    if cfg!(feature = "rpi") {
+        BackendKind::Rpi
+    } else if cfg!(feature = "sdl") {
+        BackendKind::Sdl
+    } else {
+        BackendKind::Terminal
+    }
+}
+
+fn default_scale() -> u32 {
+    4
+}
+
+fn default_file_format() -> FileFormat {
+    FileFormat::Png
+}
+
+fn default_file_path() -> String {
+    "frames".to_string()
+}
+
+fn default_every() -> u32 {
+    1
+}
+
+fn default_terminal_fps() -> u32 {
+    5
+}
+
+fn default_web_enabled() -> bool {
+    true
+}
+
+fn default_web_address() -> String {
+    "0.0.0.0:8080".to_string()
+}
+
+fn default_web_assets() -> String {
+    "html".to_string()
+}
+
+fn default_realtime_refresh() -> u64 {
+    30
+}
+
+fn default_capture_dir() -> String {
+    "captures".to_string()
+}
+
+fn default_provider() -> ProviderKind {
+    ProviderKind::Connections
+}
+
+fn default_journey() -> JourneyFormat {
+    JourneyFormat::Compact
+}
+
+fn default_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+fn default_limit() -> u32 {
+    10
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            refresh_interval: default_refresh_interval(),
+            fps: default_fps(),
+            second_num_wait: default_second_num_wait(),
+            font: default_font(),
+            state_dir: default_state_dir(),
+            api_url: default_api_url(),
+        }
+    }
+}
+
+impl Default for QuotaConfig {
+    fn default() -> QuotaConfig {
+        QuotaConfig { daily_limit: default_daily_limit() }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> DisplayConfig {
+        DisplayConfig {
+            backend: default_backend(),
+            scale: default_scale(),
+            video_driver: None,
+            mirror: vec![],
+            file: FileOutputConfig::default(),
+            terminal: TerminalConfig::default(),
+        }
+    }
+}
+
+impl Default for TerminalConfig {
+    fn default() -> TerminalConfig {
+        TerminalConfig { fps: default_terminal_fps() }
+    }
+}
+
+impl Default for WebConfig {
+    fn default() -> WebConfig {
+        WebConfig {
+            enabled: default_web_enabled(),
+            address: default_web_address(),
+            assets: default_web_assets(),
+        }
+    }
+}
+
+impl Default for FileOutputConfig {
+    fn default() -> FileOutputConfig {
+        FileOutputConfig {
+            format: default_file_format(),
+            path: default_file_path(),
+            every: default_every(),
+            start: 0,
+            duration: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.refresh_interval)
+    }
+
+    pub fn frame_time(&self) -> Duration {
+        Duration::from_micros(1_000_000 / self.fps as u64)
+    }
+}
+
+impl RealtimeConfig {
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.refresh_interval)
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config {}: {e}", path.display()))?;
+        Config::parse(&text).map_err(|e| format!("invalid config {}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        // toml errors already carry the line, the column and the offending key
+        let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let settings = &self.settings;
+        if settings.fps == 0 || settings.fps > MAX_FPS {
+            return Err(format!("settings.fps: must be between 1 and {MAX_FPS} (got {})", settings.fps));
+        }
+        if settings.refresh_interval == 0 {
+            return Err("settings.refresh_interval: must be at least 1 second".to_string());
+        }
+        if settings.font.is_empty() {
+            return Err("settings.font: must not be empty".to_string());
+        }
+        if settings.state_dir.trim().is_empty() {
+            return Err("settings.state_dir: must not be empty".to_string());
+        }
+        let api_url = reqwest::Url::parse(&settings.api_url);
+        if !api_url.is_ok_and(|url| url.scheme() == "http" || url.scheme() == "https") {
+            return Err(format!("settings.api_url: expected an http(s) URL, got `{}`", settings.api_url));
+        }
+        if self.quota.daily_limit == 0 {
+            return Err("quota.daily_limit: must be at least 1".to_string());
+        }
+
+        if self.display.scale == 0 || self.display.scale > 32 {
+            return Err(format!("display.scale: must be between 1 and 32 (got {})", self.display.scale));
+        }
+        for (i, kind) in self.display.mirror.iter().enumerate() {
+            if *kind == self.display.backend || self.display.mirror[..i].contains(kind) {
+                return Err(format!("display.mirror[{i}]: {kind:?} backend is used twice"));
+            }
+        }
+        let terminal_fps = self.display.terminal.fps;
+        if terminal_fps == 0 || terminal_fps > MAX_FPS {
+            return Err(format!("display.terminal.fps: must be between 1 and {MAX_FPS} (got {terminal_fps})"));
+        }
+        let file = &self.display.file;
+        if file.path.is_empty() {
+            return Err("display.file.path: must not be empty".to_string());
+        }
+        if file.every == 0 {
+            return Err("display.file.every: must be at least 1".to_string());
+        }
+        if file.duration == Some(0) {
+            return Err("display.file.duration: must be at least 1 second".to_string());
+        }
+        if file.format == FileFormat::Apng {
+            let duration = match file.duration {
+                Some(duration) => { duration }
+                None => { return Err("display.file.duration: required with format = \"apng\"".to_string()); }
+            };
+            let frames = duration * settings.fps as u64 / file.every as u64;
+            if frames > MAX_APNG_FRAMES {
+                return Err(format!(
+                    "display.file.duration: {frames} APNG frames, at most {MAX_APNG_FRAMES} are kept in memory (raise `every` or shorten `duration`)"
+                ));
+            }
+        }
+
+        if self.web.address.parse::<SocketAddr>().is_err() {
+            return Err(format!("web.address: `{}` is not an ip:port address", self.web.address));
+        }
+
+        if let Some(gtfs) = &self.gtfs {
+            if gtfs.path.trim().is_empty() {
+                return Err("gtfs.path: must not be empty".to_string());
+            }
+        }
+        if let Some(realtime) = &self.realtime {
+            if realtime.source.trim().is_empty() {
+                return Err("realtime.source: must not be empty".to_string());
+            }
+            if realtime.refresh_interval == 0 {
+                return Err("realtime.refresh_interval: must be at least 1 second".to_string());
+            }
+            if self.gtfs.is_none() {
+                return Err("realtime: the predictions refer to the trips of the timetable, a [gtfs] table is needed".to_string());
+            }
+        }
+
+        if let Some(capture) = &self.capture {
+            if capture.dir.trim().is_empty() {
+                return Err("capture.dir: must not be empty".to_string());
+            }
+        }
+
+        self.matrix.validate().map_err(|e| format!("matrix.{e}"))?;
+
+        if self.pages.is_empty() {
+            return Err("pages: at least one [[pages]] table is required".to_string());
+        }
+        for (i, page) in self.pages.iter().enumerate() {
+            if page.entries.is_empty() {
+                return Err(format!("pages[{i}].entries: at least one entry is required"));
+            }
+            if page.dwell.is_some() && page.cycles.is_some() {
+                return Err(format!("pages[{i}]: dwell and cycles cannot be used together"));
+            }
+            if page.dwell == Some(0) {
+                return Err(format!("pages[{i}].dwell: must be at least 1 second"));
+            }
+            if page.cycles == Some(0) {
+                return Err(format!("pages[{i}].cycles: must be at least 1"));
+            }
+            for (j, entry) in page.entries.iter().enumerate() {
+                entry.validate().map_err(|e| format!("pages[{i}].entries[{j}].{e}"))?;
+                let uses_gtfs = entry.provider == ProviderKind::Gtfs || entry.fallback == Some(ProviderKind::Gtfs);
+                if uses_gtfs && self.gtfs.is_none() {
+                    return Err(format!("pages[{i}].entries[{j}].provider: gtfs needs a [gtfs] table with the timetable path"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EntryConfig {
+    // errors are relative to the entry, the caller prefixes them with its position
+    fn validate(&self) -> Result<(), String> {
+        if self.label.trim().is_empty() {
+            return Err("label: must not be empty".to_string());
+        }
+        if self.from.trim().is_empty() {
+            return Err("from: must not be empty".to_string());
+        }
+        let max_limit = match self.provider {
+            ProviderKind::Connections => {
+                if self.to.trim().is_empty() {
+                    return Err("to: must not be empty".to_string());
+                }
+                if !self.lines.is_empty() || !self.categories.is_empty() || !self.destinations.is_empty() {
+                    return Err("lines, categories and destinations are only used by the stationboard".to_string());
+                }
+                MAX_LIMIT
+            }
+            ProviderKind::Stationboard | ProviderKind::Gtfs => {
+                if self.journey == JourneyFormat::Detailed {
+                    return Err("journey: detailed needs the legs of the connections provider".to_string());
+                }
+                MAX_STATIONBOARD_LIMIT
+            }
+        };
+        let kinds = [Some(self.provider), self.fallback];
+        let connections = kinds.contains(&Some(ProviderKind::Connections));
+        if !connections && (!self.via.is_empty() || self.direct || self.date.is_some() || self.time.is_some() || self.is_arrival_time) {
+            return Err("via, direct, date, time and is_arrival_time are only used by the connections provider".to_string());
+        }
+        if !connections && !kinds.contains(&Some(ProviderKind::Stationboard)) && !self.transportations.is_empty() {
+            return Err("transportations: only used by the connections and stationboard providers".to_string());
+        }
+        if self.via.len() > MAX_VIA {
+            return Err(format!("via: at most {MAX_VIA} stations (got {})", self.via.len()));
+        }
+        if let Some(k) = self.via.iter().position(|v| v.trim().is_empty()) {
+            return Err(format!("via[{k}]: must not be empty"));
+        }
+        for (k, transportation) in self.transportations.iter().enumerate() {
+            if !TRANSPORTATIONS.contains(&transportation.as_str()) {
+                return Err(format!("transportations[{k}]: expected one of {}, got `{transportation}`", TRANSPORTATIONS.join(", ")));
+            }
+        }
+        if let Some(date) = &self.date {
+            if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+                return Err(format!("date: expected YYYY-MM-DD, got `{date}`"));
+            }
+        }
+        if let Some(time) = &self.time {
+            if NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+                return Err(format!("time: expected HH:MM, got `{time}`"));
+            }
+        }
+        if self.is_arrival_time && self.time.is_none() {
+            return Err("is_arrival_time: needs a time".to_string());
+        }
+        if self.group_by_line && self.journey == JourneyFormat::Detailed {
+            return Err("group_by_line: cannot be used with journey = \"detailed\"".to_string());
+        }
+        if self.group_size == 0 || self.group_size > MAX_GROUP_SIZE {
+            return Err(format!("group_size: must be between 1 and {MAX_GROUP_SIZE} (got {})", self.group_size));
+        }
+        if self.fallback == Some(self.provider) {
+            return Err("fallback: must differ from provider".to_string());
+        }
+        if self.limit == 0 || self.limit > max_limit {
+            return Err(format!("limit: must be between 1 and {max_limit} (got {})", self.limit));
+        }
+        for (k, field) in self.fields.iter().flatten().enumerate() {
+            if field.trim().is_empty() {
+                return Err(format!("fields[{k}]: must not be empty"));
+            }
+        }
+        for (key, values) in [("lines", &self.lines), ("categories", &self.categories), ("destinations", &self.destinations)] {
+            if let Some(k) = values.iter().position(|v| v.trim().is_empty()) {
+                return Err(format!("{key}[{k}]: must not be empty"));
+            }
+        }
+        if self.priority == 0 || self.priority > MAX_PRIORITY {
+            return Err(format!("priority: must be between 1 and {MAX_PRIORITY} (got {})", self.priority));
+        }
+        ActiveHours::parse(&self.active_hours).map_err(|e| format!("active_hours{e}"))?;
+        if let Some(run_time) = self.run_time {
+            if run_time > self.walk_time {
+                return Err(format!("run_time: must not be longer than walk_time ({run_time} > {})", self.walk_time));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `tables` before the pages, `entry` keys added to a valid connections entry
+    fn parse(tables: &str, entry: &str) -> Result<Config, String> {
+        Config::parse(&format!(
+            "{tables}\n[[pages]]\n[[pages.entries]]\nlabel = \"T2\"\nfrom = \"Zurich, HB\"\nto = \"Zurich, Farbhof\"\n{entry}"
+        ))
+    }
+
+    fn error(tables: &str, entry: &str) -> String {
+        parse(tables, entry).unwrap_err()
+    }
+
+    #[test]
+    fn the_example_config_is_valid() {
+        Config::load(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml"))).unwrap();
+    }
+
+    #[test]
+    fn defaults_fill_a_minimal_config() {
+        let config = parse("", "").unwrap();
+        assert_eq!(config.settings.font, "fonts/5x7.bdf");
+        assert_eq!(config.quota.daily_limit, 1000);
+        let entry = &config.pages[0].entries[0];
+        assert_eq!((entry.provider, entry.limit, entry.priority), (ProviderKind::Connections, 10, 1));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(error("", "lmit = 3").contains("unknown field `lmit`"));
+        assert!(error("[settings]\nfsp = 30", "").contains("unknown field `fsp`"));
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        assert_eq!(Config::parse("").unwrap_err(), "pages: at least one [[pages]] table is required");
+        assert_eq!(error("", "limit = 17"), "pages[0].entries[0].limit: must be between 1 and 16 (got 17)");
+        assert_eq!(error("[settings]\nfps = 0", ""), "settings.fps: must be between 1 and 120 (got 0)");
+    }
+
+    #[test]
+    fn apng_recordings_are_bounded() {
+        assert_eq!(error("[display.file]\nformat = \"apng\"", ""), "display.file.duration: required with format = \"apng\"");
+        assert!(error("[display.file]\nformat = \"apng\"\nduration = 60", "").starts_with("display.file.duration: 1800 APNG frames"));
+        assert!(parse("[display.file]\nformat = \"apng\"\nduration = 60\nevery = 2", "").is_ok());
+        assert!(parse("[display.file]\nformat = \"gif\"", "").is_ok());
+    }
+
+    #[test]
+    fn a_backend_is_not_mirrored_twice() {
+        assert_eq!(error("[display]\nbackend = \"file\"\nmirror = [\"terminal\", \"file\"]", ""),
+                   "display.mirror[1]: File backend is used twice");
+    }
+
+    #[test]
+    fn matrix_options_are_range_checked() {
+        assert_eq!(error("[matrix]\nrows = 31", ""), "matrix.rows: must be an even number (got 31)");
+        assert_eq!(error("[matrix]\nmultiplexing = 19", ""), "matrix.multiplexing: must be between 0 and 18 (got 19)");
+        assert_eq!(error("[matrix]\nlimit_refresh_rate_hz = 10001", ""),
+                   "matrix.limit_refresh_rate_hz: must be between 0 and 10000 (got 10001)");
+        assert!(parse("[matrix]\nmultiplexing = 18\nlimit_refresh_rate_hz = 120", "").is_ok());
+    }
+
+    #[test]
+    fn api_url_must_be_http() {
+        assert_eq!(error("[settings]\napi_url = \"ftp://example.com/v1/\"", ""),
+                   "settings.api_url: expected an http(s) URL, got `ftp://example.com/v1/`");
+        assert!(parse("[settings]\napi_url = \"http://127.0.0.1:18080/v1/\"", "").is_ok());
+    }
+
+    #[test]
+    fn stationboard_filters_need_the_stationboard() {
+        assert_eq!(
+            error("", "lines = [\"2\"]"),
+            "pages[0].entries[0].lines, categories and destinations are only used by the stationboard"
+        );
+        assert_eq!(error("", "provider = \"stationboard\"\nlines = [\"\"]"), "pages[0].entries[0].lines[0]: must not be empty");
+        assert!(parse("", "provider = \"stationboard\"\nlimit = 40\nlines = [\"2\"]").is_ok());
+    }
+
+    #[test]
+    fn gtfs_needs_a_timetable() {
+        assert_eq!(
+            error("", "fallback = \"gtfs\""),
+            "pages[0].entries[0].provider: gtfs needs a [gtfs] table with the timetable path"
+        );
+        assert_eq!(error("", "fallback = \"connections\""), "pages[0].entries[0].fallback: must differ from provider");
+        assert!(parse("[gtfs]\npath = \"gtfs.zip\"", "fallback = \"gtfs\"").is_ok());
+    }
+
+    #[test]
+    fn realtime_needs_a_timetable() {
+        assert!(error("[realtime]\nsource = \"feed.pb\"", "").starts_with("realtime: the predictions refer to the trips"));
+        assert!(parse("[gtfs]\npath = \"gtfs.zip\"\n[realtime]\nsource = \"feed.pb\"", "").is_ok());
+    }
+
+    #[test]
+    fn quota_settings_are_checked() {
+        assert_eq!(error("", "active_hours = [\"7-9\"]"), "pages[0].entries[0].active_hours[0]: expected HH:MM-HH:MM, got `7-9`");
+        assert_eq!(error("", "priority = 11"), "pages[0].entries[0].priority: must be between 1 and 10 (got 11)");
+        assert_eq!(error("[quota]\ndaily_limit = 0", ""), "quota.daily_limit: must be at least 1");
+    }
+
+    #[test]
+    fn run_time_is_at_most_walk_time() {
+        assert_eq!(error("", "run_time = 60"), "pages[0].entries[0].run_time: must not be longer than walk_time (60 > 0)");
+        assert!(parse("", "walk_time = 300\nrun_time = 120").is_ok());
+    }
+
+    #[test]
+    fn connection_options_need_the_connections_provider() {
+        assert_eq!(
+            error("", "provider = \"stationboard\"\nvia = [\"Bern\"]"),
+            "pages[0].entries[0].via, direct, date, time and is_arrival_time are only used by the connections provider"
+        );
+        assert_eq!(error("", "transportations = [\"boat\"]"),
+                   "pages[0].entries[0].transportations[0]: expected one of ".to_string() + &TRANSPORTATIONS.join(", ") + ", got `boat`");
+        assert_eq!(error("", "time = \"8h\""), "pages[0].entries[0].time: expected HH:MM, got `8h`");
+        assert!(parse("", "via = [\"Bern\"]\ndirect = true\ntransportations = [\"train\"]").is_ok());
+    }
+}
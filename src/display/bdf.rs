@@ -0,0 +1,121 @@
+// Minimal BDF font reader, drawing glyphs the same way the rpi-rgb-led-matrix
+// library does so the software backends match what the panel shows.
+
+use std::collections::HashMap;
+use std::fs;
+
+const REPLACEMENT_CHARACTER: u32 = 0xFFFD;
+
+struct Glyph {
+    device_width: i32,
+    height: i32,
+    x_offset: i32,
+    y_offset: i32,
+    // one entry per row, the leftmost pixel is the most significant bit
+    rows: Vec<u64>,
+}
+
+pub struct BdfFont {
+    glyphs: HashMap<u32, Glyph>,
+}
+
+fn parse_numbers(line: &str, keyword: &str) -> Option<Vec<i32>> {
+    line.strip_prefix(keyword)?
+        .split_whitespace()
+        .map(|n| n.parse().ok())
+        .collect()
+}
+
+impl BdfFont {
+    pub fn load(path: &str) -> Result<BdfFont, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot load font {path}: {e}"))?;
+        BdfFont::parse(&text).map_err(|e| format!("cannot load font {path}: {e}"))
+    }
+
+    pub fn parse(text: &str) -> Result<BdfFont, String> {
+        let mut glyphs = HashMap::new();
+
+        let mut encoding: Option<u32> = None;
+        let mut glyph: Option<Glyph> = None;
+        let mut row_bits = 0;
+        let mut in_bitmap = false;
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let bad_line = || format!("line {}: cannot parse `{line}`", line_num + 1);
+
+            if in_bitmap {
+                if line == "ENDCHAR" {
+                    in_bitmap = false;
+                    if let (Some(code), Some(g)) = (encoding.take(), glyph.take()) {
+                        glyphs.insert(code, g);
+                    }
+                    continue;
+                }
+                let g = glyph.as_mut().ok_or_else(bad_line)?;
+                let value = u64::from_str_radix(line, 16).map_err(|_| bad_line())?;
+                // rows are padded to full bytes, align them on the left of the u64
+                let aligned = value.checked_shl(64 - row_bits).unwrap_or(0);
+                g.rows.push(aligned.checked_shr(g.x_offset.max(0) as u32).unwrap_or(0));
+            } else if let Some(numbers) = parse_numbers(line, "ENCODING") {
+                let code = *numbers.first().ok_or_else(bad_line)?;
+                // negative encodings are glyphs without a codepoint
+                encoding = u32::try_from(code).ok();
+                glyph = Some(Glyph { device_width: 0, height: 0, x_offset: 0, y_offset: 0, rows: vec![] });
+            } else if let Some(numbers) = parse_numbers(line, "DWIDTH") {
+                let g = glyph.as_mut().ok_or_else(bad_line)?;
+                g.device_width = *numbers.first().ok_or_else(bad_line)?;
+            } else if let Some(numbers) = parse_numbers(line, "BBX") {
+                let g = glyph.as_mut().ok_or_else(bad_line)?;
+                if numbers.len() != 4 || !(0..=64).contains(&numbers[0]) {
+                    return Err(bad_line());
+                }
+                g.height = numbers[1];
+                g.x_offset = numbers[2];
+                g.y_offset = numbers[3];
+                row_bits = (numbers[0] as u32).div_ceil(8) * 8;
+            } else if line == "BITMAP" {
+                if glyph.is_none() {
+                    return Err(bad_line());
+                }
+                in_bitmap = true;
+            }
+        }
+
+        if glyphs.is_empty() {
+            return Err("no glyph found".to_string());
+        }
+        Ok(BdfFont { glyphs })
+    }
+
+    fn find_glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&(c as u32)).or_else(|| self.glyphs.get(&REPLACEMENT_CHARACTER))
+    }
+
+    // advance of `c`, the fonts used on the panels are monospaced
+    pub fn char_width(&self, c: char) -> i32 {
+        self.find_glyph(c).map(|g| g.device_width).unwrap_or(0)
+    }
+
+    // calls `set_pixel` for every lit pixel of `text` drawn with its baseline at `y`,
+    // returns the x position after the last character
+    pub fn draw_text(&self, x: i32, y: i32, text: &str, mut set_pixel: impl FnMut(i32, i32)) -> i32 {
+        let mut x_pos = x;
+        for c in text.chars() {
+            let g = match self.find_glyph(c) {
+                Some(g) => { g }
+                None => { continue; }
+            };
+            let top = y - g.height - g.y_offset;
+            for (row_index, row) in g.rows.iter().enumerate() {
+                for col in 0..g.device_width.min(64) {
+                    if row & (1u64 << (63 - col)) != 0 {
+                        set_pixel(x_pos + col, top + row_index as i32);
+                    }
+                }
+            }
+            x_pos += g.device_width;
+        }
+        x_pos
+    }
+}
@@ -0,0 +1,167 @@
+// Headless backend writing the frames to disk: a numbered PNG sequence, an
+// animated GIF or an animated PNG. Handy for bug reports, README demos and
+// checking a layout change without the panels.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, RgbImage};
+
+use crate::config::{FileFormat, FileOutputConfig};
+use crate::display::framebuffer::{delegate_to_framebuffer, FrameBuffer};
+use crate::display::DisplayBackend;
+
+// 1 is the best quality and the slowest, 10 is a good trade-off for small frames
+const GIF_SPEED: i32 = 10;
+
+enum Output {
+    PngSequence(PathBuf),
+    Gif(GifEncoder<BufWriter<File>>),
+    // the frame count is part of the APNG header, frames are kept until the end
+    Apng(PathBuf, Vec<RgbImage>),
+}
+
+pub struct FileBackend {
+    framebuffer: FrameBuffer,
+    output: Option<Output>,
+    every: u64,
+    start: Duration,
+    end: Option<Duration>,
+    frame_delay: Duration,
+    started_at: SystemTime,
+    frame_index: u64,
+    written: u64,
+}
+
+impl FileBackend {
+    pub fn new(width: u32, height: u32, font_path: &str, config: &FileOutputConfig, frame_time: Duration)
+               -> Result<FileBackend, String> {
+        let path = PathBuf::from(&config.path);
+        let output = match config.format {
+            FileFormat::Png => {
+                fs::create_dir_all(&path).map_err(|e| format!("cannot create {}: {e}", path.display()))?;
+                Output::PngSequence(path)
+            }
+            FileFormat::Gif => {
+                let file = File::create(&path).map_err(|e| format!("cannot create {}: {e}", path.display()))?;
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+                encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+                Output::Gif(encoder)
+            }
+            FileFormat::Apng => Output::Apng(path, vec![]),
+        };
+
+        let start = Duration::from_secs(config.start);
+        Ok(FileBackend {
+            framebuffer: FrameBuffer::new(width, height, font_path)?,
+            output: Some(output),
+            every: config.every as u64,
+            start,
+            end: config.duration.map(|duration| start + Duration::from_secs(duration)),
+            frame_delay: frame_time * config.every,
+            started_at: SystemTime::now(),
+            frame_index: 0,
+            written: 0,
+        })
+    }
+
+    fn write_frame(&mut self, frame: RgbImage) -> Result<(), String> {
+        match &mut self.output {
+            Some(Output::PngSequence(dir)) => {
+                let path = dir.join(format!("frame_{:06}.png", self.written));
+                frame.save(&path).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+            }
+            Some(Output::Gif(encoder)) => {
+                let rgba = DynamicImage::ImageRgb8(frame).into_rgba8();
+                let delay = Delay::from_saturating_duration(self.frame_delay);
+                encoder.encode_frame(Frame::from_parts(rgba, 0, 0, delay)).map_err(|e| e.to_string())?;
+            }
+            Some(Output::Apng(_, frames)) => {
+                frames.push(frame);
+            }
+            None => {}
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    // writes what still needs to be written, nothing is recorded afterwards
+    fn finish(&mut self) -> Result<(), String> {
+        match self.output.take() {
+            // the gif trailer is written when the encoder is dropped
+            Some(Output::Gif(encoder)) => { drop(encoder); }
+            Some(Output::Apng(path, frames)) => { write_apng(&path, &frames, self.frame_delay)?; }
+            _ => {}
+        }
+        log!("\n{} frame(s) recorded", self.written);
+        Ok(())
+    }
+}
+
+fn write_apng(path: &PathBuf, frames: &[RgbImage], frame_delay: Duration) -> Result<(), String> {
+    let first = match frames.first() {
+        Some(e) => { e }
+        None => { return Ok(()); }
+    };
+    let write = || -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), first.width(), first.height());
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0)?;
+        encoder.set_frame_delay(frame_delay.as_millis().min(u16::MAX as u128) as u16, 1000)?;
+        let mut writer = encoder.write_header()?;
+        for frame in frames {
+            writer.write_image_data(frame.as_raw())?;
+        }
+        writer.finish()
+    };
+    write().map_err(|e| format!("cannot write {}: {e}", path.display()))
+}
+
+impl DisplayBackend for FileBackend {
+    delegate_to_framebuffer!(framebuffer);
+
+    fn present(&mut self) {
+        let index = self.frame_index;
+        self.frame_index += 1;
+        if self.output.is_none() {
+            return;
+        }
+
+        let elapsed = self.started_at.elapsed().unwrap_or_default();
+        if let Some(end) = self.end {
+            if elapsed >= end {
+                if let Err(e) = self.finish() {
+                    log!("{e}");
+                }
+                return;
+            }
+        }
+        if elapsed < self.start || !index.is_multiple_of(self.every) {
+            return;
+        }
+
+        let frame = self.framebuffer.frame();
+        if let Err(e) = self.write_frame(frame) {
+            log!("{e}");
+        }
+    }
+
+    // once the recording window is over there is nothing left to do
+    fn is_closed(&self) -> bool {
+        self.output.is_none()
+    }
+}
+
+impl Drop for FileBackend {
+    fn drop(&mut self) {
+        if self.output.is_some() {
+            if let Err(e) = self.finish() {
+                log!("{e}");
+            }
+        }
+    }
+}
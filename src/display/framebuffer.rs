@@ -0,0 +1,109 @@
+// In-memory canvas shared by the backends that do not drive real leds.
+
+use image::{Rgb as Pixel, RgbImage};
+
+use crate::display::bdf::BdfFont;
+use crate::display::Rgb;
+
+pub struct FrameBuffer {
+    image: RgbImage,
+    font: BdfFont,
+    brightness: u8,
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32, font_path: &str) -> Result<FrameBuffer, String> {
+        Ok(FrameBuffer {
+            image: RgbImage::new(width, height),
+            font: BdfFont::load(font_path)?,
+            brightness: 100,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn fill(&mut self, (r, g, b): Rgb) {
+        for pixel in self.image.pixels_mut() {
+            *pixel = Pixel([r, g, b]);
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, (r, g, b): Rgb) {
+        if x < 0 || y < 0 || x >= self.image.width() as i32 || y >= self.image.height() as i32 {
+            return;
+        }
+        self.image.put_pixel(x as u32, y as u32, Pixel([r, g, b]));
+    }
+
+    pub fn draw_text(&mut self, x: i32, y: i32, color: Rgb, text: &str) -> i32 {
+        let mut lit_pixels = vec![];
+        let end = self.font.draw_text(x, y, text, |px, py| lit_pixels.push((px, py)));
+        for (px, py) in lit_pixels {
+            self.set_pixel(px, py, color);
+        }
+        end
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness.min(100);
+    }
+
+    // the image as the panel would show it, with the brightness applied
+    pub fn frame(&self) -> RgbImage {
+        let mut frame = self.image.clone();
+        if self.brightness < 100 {
+            for pixel in frame.pixels_mut() {
+                for c in pixel.0.iter_mut() {
+                    *c = (*c as u16 * self.brightness as u16 / 100) as u8;
+                }
+            }
+        }
+        frame
+    }
+}
+
+// implements DisplayBackend for a type holding a FrameBuffer in `$field`,
+// only `present` is left to the backend
+macro_rules! delegate_to_framebuffer {
+    ($field:ident) => {
+        fn width(&self) -> u32 {
+            self.$field.width()
+        }
+
+        fn height(&self) -> u32 {
+            self.$field.height()
+        }
+
+        fn fill(&mut self, color: crate::display::Rgb) {
+            self.$field.fill(color)
+        }
+
+        fn set_pixel(&mut self, x: i32, y: i32, color: crate::display::Rgb) {
+            self.$field.set_pixel(x, y, color)
+        }
+
+        fn draw_text(&mut self, x: i32, y: i32, color: crate::display::Rgb, text: &str) -> i32 {
+            self.$field.draw_text(x, y, color, text)
+        }
+
+        fn brightness(&self) -> u8 {
+            self.$field.brightness()
+        }
+
+        fn set_brightness(&mut self, brightness: u8) {
+            self.$field.set_brightness(brightness)
+        }
+    };
+}
+
+pub(crate) use delegate_to_framebuffer;
@@ -0,0 +1,72 @@
+// Sends every drawing call to a primary backend and to any number of mirrors,
+// e.g. the led panels mirrored in the terminal of an SSH session.
+
+use image::RgbaImage;
+
+use crate::display::{DisplayBackend, Rgb};
+use crate::WebEvent;
+
+pub struct MirrorBackend {
+    primary: Box<dyn DisplayBackend>,
+    mirrors: Vec<Box<dyn DisplayBackend>>,
+}
+
+impl MirrorBackend {
+    pub fn new(primary: Box<dyn DisplayBackend>, mirrors: Vec<Box<dyn DisplayBackend>>) -> MirrorBackend {
+        MirrorBackend { primary, mirrors }
+    }
+
+    fn all(&mut self) -> impl Iterator<Item = &mut Box<dyn DisplayBackend>> {
+        std::iter::once(&mut self.primary).chain(self.mirrors.iter_mut())
+    }
+}
+
+impl DisplayBackend for MirrorBackend {
+    fn width(&self) -> u32 {
+        self.primary.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.primary.height()
+    }
+
+    fn fill(&mut self, color: Rgb) {
+        self.all().for_each(|backend| backend.fill(color));
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32, color: Rgb) {
+        self.all().for_each(|backend| backend.set_pixel(x, y, color));
+    }
+
+    fn draw_text(&mut self, x: i32, y: i32, color: Rgb, text: &str) -> i32 {
+        for mirror in &mut self.mirrors {
+            mirror.draw_text(x, y, color, text);
+        }
+        self.primary.draw_text(x, y, color, text)
+    }
+
+    fn present(&mut self) {
+        self.all().for_each(|backend| backend.present());
+    }
+
+    fn brightness(&self) -> u8 {
+        self.primary.brightness()
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.all().for_each(|backend| backend.set_brightness(brightness));
+    }
+
+    fn poll_events(&mut self) -> Vec<WebEvent> {
+        self.all().flat_map(|backend| backend.poll_events()).collect()
+    }
+
+    // a mirror that is done (e.g. a finished recording) does not stop the display
+    fn is_closed(&self) -> bool {
+        self.primary.is_closed()
+    }
+
+    fn blit_image(&mut self, x: i32, y: i32, image: &RgbaImage) {
+        self.all().for_each(|backend| backend.blit_image(x, y, image));
+    }
+}
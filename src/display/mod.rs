@@ -0,0 +1,61 @@
+// Everything that ends up on a screen goes through a DisplayBackend, the
+// dashboard only knows about this trait and the size of the canvas.
+
+use image::RgbaImage;
+
+use crate::WebEvent;
+
+pub mod bdf;
+pub mod file;
+mod framebuffer;
+pub mod mirror;
+#[cfg(feature = "rpi")]
+pub mod rgbmatrix;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod terminal;
+
+pub type Rgb = (u8, u8, u8);
+
+pub trait DisplayBackend {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+
+    fn fill(&mut self, color: Rgb);
+    // the dashboard only draws text for now
+    #[allow(dead_code)]
+    fn set_pixel(&mut self, x: i32, y: i32, color: Rgb);
+
+    // draws `text` with its baseline at `y`, returns the x position after the last character
+    fn draw_text(&mut self, x: i32, y: i32, color: Rgb, text: &str) -> i32;
+
+    // shows what has been drawn since the previous call
+    fn present(&mut self);
+
+    // brightness in percent, 0 to 100
+    fn brightness(&self) -> u8;
+    fn set_brightness(&mut self, brightness: u8);
+
+    // user input collected by the backend since the previous call (keyboard for the simulator)
+    fn poll_events(&mut self) -> Vec<WebEvent> {
+        vec![]
+    }
+
+    // true once the user asked to quit, e.g. by closing the simulator window
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    // alpha is applied against black, that is what an unlit led looks like
+    #[allow(dead_code)]
+    fn blit_image(&mut self, x: i32, y: i32, image: &RgbaImage) {
+        for (px, py, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a == 0 {
+                continue;
+            }
+            let scale = |c: u8| (c as u16 * a as u16 / 255) as u8;
+            self.set_pixel(x + px as i32, y + py as i32, (scale(r), scale(g), scale(b)));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// DisplayBackend driving the panels through the rpi-rgb-led-matrix C library.
+
+use std::ffi::CString;
+use std::os::raw::c_int;
+
+use crate::display::{DisplayBackend, Rgb};
+use crate::matrix::MatrixConfig;
+
+mod bindings {
+    #![allow(non_upper_case_globals)]
+    #![allow(non_camel_case_types)]
+    #![allow(non_snake_case)]
+    #![allow(dead_code)]
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
+pub struct RgbMatrixBackend {
+    matrix: *mut bindings::RGBLedMatrix,
+    // we draw on the offscreen canvas and swap it on vsync to avoid tearing
+    canvas: *mut bindings::LedCanvas,
+    font: *mut bindings::LedFont,
+    width: u32,
+    height: u32,
+}
+
+impl RgbMatrixBackend {
+    pub fn new(font_path: &str, options: &MatrixConfig) -> Result<RgbMatrixBackend, String> {
+        let c_string = |key: &str, value: &str| CString::new(value).map_err(|_| format!("matrix.{key}: contains a NUL byte"));
+        let hardware_mapping = c_string("hardware_mapping", &options.hardware_mapping)?;
+        let led_rgb_sequence = c_string("led_rgb_sequence", &options.led_rgb_sequence)?;
+        let pixel_mapper_config = c_string("pixel_mapper_config", &options.pixel_mapper_config)?;
+        let panel_type = c_string("panel_type", &options.panel_type)?;
+        // 0 lets the library pick its default
+        let mut rgb_option = bindings::RGBLedMatrixOptions {
+            hardware_mapping: hardware_mapping.as_ptr(),
+            rows: options.rows as c_int,
+            cols: options.cols as c_int,
+            chain_length: options.chain_length as c_int,
+            parallel: options.parallel as c_int,
+            pwm_bits: options.pwm_bits.unwrap_or(0) as c_int,
+            pwm_lsb_nanoseconds: options.pwm_lsb_nanoseconds.unwrap_or(0) as c_int,
+            pwm_dither_bits: options.dither.unwrap_or(0) as c_int,
+            brightness: options.brightness.unwrap_or(0) as c_int,
+            scan_mode: options.scan_mode as c_int,
+            row_address_type: options.row_address_type as c_int,
+            multiplexing: options.multiplexing as c_int,
+            disable_hardware_pulsing: options.disable_hardware_pulsing,
+            show_refresh_rate: options.show_refresh_rate,
+            inverse_colors: options.inverse_colors,
+            led_rgb_sequence: led_rgb_sequence.as_ptr(),
+            pixel_mapper_config: pixel_mapper_config.as_ptr(),
+            panel_type: panel_type.as_ptr(),
+            limit_refresh_rate_hz: options.limit_refresh_rate_hz as c_int,
+        };
+
+        let drop_priv_user = c_string("drop_priv_user", &options.drop_priv_user)?;
+        let drop_priv_group = c_string("drop_priv_group", &options.drop_priv_group)?;
+        let mut rgb_runtime_opt = bindings::RGBLedRuntimeOptions {
+            gpio_slowdown: options.gpio_slowdown as c_int,
+            daemon: options.daemon as c_int,
+            drop_privileges: options.drop_privileges as c_int,
+            do_gpio_init: false,
+            drop_priv_user: drop_priv_user.as_ptr(),
+            drop_priv_group: drop_priv_group.as_ptr(),
+        };
+
+        let font_c_path = CString::new(font_path).map_err(|e| e.to_string())?;
+        let mut width: c_int = 0;
+        let mut height: c_int = 0;
+        let (matrix, canvas, font);
+        // the options are only read during the call, the CStrings above outlive it
+        unsafe {
+            matrix = bindings::led_matrix_create_from_options_and_rt_options(&mut rgb_option, &mut rgb_runtime_opt);
+            if matrix.is_null() {
+                return Err("cannot initialise the led matrix".to_string());
+            }
+            canvas = bindings::led_matrix_create_offscreen_canvas(matrix);
+            bindings::led_canvas_get_size(canvas, &mut width, &mut height);
+            font = bindings::load_font(font_c_path.as_ptr());
+            if font.is_null() {
+                bindings::led_matrix_delete(matrix);
+                return Err(format!("cannot load font {font_path}"));
+            }
+        }
+
+        Ok(RgbMatrixBackend {
+            matrix,
+            canvas,
+            font,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+}
+
+impl DisplayBackend for RgbMatrixBackend {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn fill(&mut self, (r, g, b): Rgb) {
+        unsafe {
+            bindings::led_canvas_fill(self.canvas, r, g, b);
+        }
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32, (r, g, b): Rgb) {
+        unsafe {
+            bindings::led_canvas_set_pixel(self.canvas, x, y, r, g, b);
+        }
+    }
+
+    fn draw_text(&mut self, x: i32, y: i32, (r, g, b): Rgb, text: &str) -> i32 {
+        let c_text = match CString::new(text) {
+            Ok(e) => { e }
+            Err(_) => { return x; }
+        };
+        unsafe {
+            x + bindings::draw_text(self.canvas, self.font, x, y, r, g, b, c_text.as_ptr(), 0)
+        }
+    }
+
+    fn present(&mut self) {
+        unsafe {
+            self.canvas = bindings::led_matrix_swap_on_vsync(self.matrix, self.canvas);
+        }
+    }
+
+    fn brightness(&self) -> u8 {
+        unsafe { bindings::led_matrix_get_brightness(self.matrix) }
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        unsafe {
+            bindings::led_matrix_set_brightness(self.matrix, brightness.min(100));
+        }
+    }
+}
+
+impl Drop for RgbMatrixBackend {
+    fn drop(&mut self) {
+        // also deletes the canvases, the library clears the panel on exit
+        unsafe {
+            bindings::led_matrix_delete(self.matrix);
+        }
+    }
+}
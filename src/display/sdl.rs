@@ -0,0 +1,114 @@
+// Desktop simulator: shows the frame the panel would display in an SDL2 window,
+// each led being drawn as a `scale` x `scale` square.
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::WindowCanvas;
+use sdl2::EventPump;
+
+use crate::display::framebuffer::{delegate_to_framebuffer, FrameBuffer};
+use crate::display::DisplayBackend;
+use crate::WebEvent;
+
+pub struct SdlBackend {
+    framebuffer: FrameBuffer,
+    canvas: WindowCanvas,
+    event_pump: EventPump,
+    events: Vec<WebEvent>,
+    closed: bool,
+}
+
+impl SdlBackend {
+    // `video_driver` selects an SDL video driver, "dummy" or "offscreen" run without any display
+    pub fn new(width: u32, height: u32, scale: u32, font_path: &str, video_driver: Option<&str>)
+               -> Result<SdlBackend, String> {
+        if let Some(driver) = video_driver {
+            sdl2::hint::set("SDL_VIDEODRIVER", driver);
+        }
+        let sdl_context = sdl2::init()?;
+        let video_subsys = sdl_context.video()?;
+
+        let window = video_subsys
+            .window("led matrix simulator", width * scale, height * scale)
+            .position_centered()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        canvas.set_logical_size(width, height).map_err(|e| e.to_string())?;
+
+        Ok(SdlBackend {
+            framebuffer: FrameBuffer::new(width, height, font_path)?,
+            canvas,
+            event_pump: sdl_context.event_pump()?,
+            events: vec![],
+            closed: false,
+        })
+    }
+
+    fn handle_events(&mut self) {
+        while let Some(event) = self.event_pump.poll_event() {
+            let keycode = match event {
+                Event::Quit { .. } => {
+                    self.closed = true;
+                    continue;
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } => { keycode }
+                _ => { continue; }
+            };
+            let web_event = match keycode {
+                Keycode::Escape | Keycode::Q => {
+                    self.closed = true;
+                    continue;
+                }
+                Keycode::Up | Keycode::Plus | Keycode::KpPlus => { WebEvent::BrightnessUp }
+                Keycode::Down | Keycode::Minus | Keycode::KpMinus => { WebEvent::BrightnessDown }
+                Keycode::Right | Keycode::N => { WebEvent::NextDest }
+                Keycode::Left | Keycode::P => { WebEvent::PrevDest }
+                Keycode::R => { WebEvent::Reset }
+                Keycode::Space => { WebEvent::TogglePlay }
+                _ => { continue; }
+            };
+            self.events.push(web_event);
+        }
+    }
+}
+
+impl DisplayBackend for SdlBackend {
+    delegate_to_framebuffer!(framebuffer);
+
+    fn present(&mut self) {
+        let frame = self.framebuffer.frame();
+        let texture_creator = self.canvas.texture_creator();
+        let texture = texture_creator
+            .create_texture_static(PixelFormatEnum::RGB24, frame.width(), frame.height())
+            .map_err(|e| e.to_string())
+            .and_then(|mut texture| {
+                texture
+                    .update(None, frame.as_raw(), frame.width() as usize * 3)
+                    .map_err(|e| e.to_string())?;
+                Ok(texture)
+            });
+        match texture {
+            Ok(texture) => {
+                self.canvas.clear();
+                if let Err(e) = self.canvas.copy(&texture, None, None) {
+                    log!("cannot copy the frame to the window: {e}");
+                }
+                self.canvas.present();
+            }
+            Err(e) => {
+                log!("cannot create the frame texture: {e}");
+            }
+        }
+        self.handle_events();
+    }
+
+    fn poll_events(&mut self) -> Vec<WebEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
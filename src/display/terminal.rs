@@ -0,0 +1,81 @@
+// Preview in a terminal: two leds per character cell using the upper half
+// block, the top led as foreground and the bottom one as background, in
+// 24-bit color. Works over SSH and on machines without a window system.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use image::RgbImage;
+
+use crate::display::framebuffer::{delegate_to_framebuffer, FrameBuffer};
+use crate::display::DisplayBackend;
+
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+
+pub struct TerminalBackend {
+    framebuffer: FrameBuffer,
+    refresh_interval: Duration,
+    last_draw: Option<Instant>,
+}
+
+impl TerminalBackend {
+    pub fn new(width: u32, height: u32, font_path: &str, fps: u32) -> Result<TerminalBackend, String> {
+        let framebuffer = FrameBuffer::new(width, height, font_path)?;
+        // clear the screen once and hide the cursor, each frame is then drawn over the previous one;
+        // only once nothing can fail, Drop shows the cursor again
+        print!("\x1b[2J\x1b[?25l");
+        Ok(TerminalBackend {
+            framebuffer,
+            refresh_interval: Duration::from_micros(1_000_000 / fps.max(1) as u64),
+            last_draw: None,
+        })
+    }
+}
+
+fn frame_to_ansi(frame: &RgbImage) -> String {
+    let mut out = String::with_capacity((frame.width() * frame.height() * 20) as usize);
+    out.push_str("\x1b[H");
+    for y in (0..frame.height()).step_by(2) {
+        let mut last_colors = None;
+        for x in 0..frame.width() {
+            let top = frame.get_pixel(x, y).0;
+            let bottom = if y + 1 < frame.height() { frame.get_pixel(x, y + 1).0 } else { [0, 0, 0] };
+            // only send the escape codes when the colors change, it makes a big difference over SSH
+            if last_colors != Some((top, bottom)) {
+                let _ = write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                               top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]);
+                last_colors = Some((top, bottom));
+            }
+            out.push(UPPER_HALF_BLOCK);
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    // remove whatever was printed below the previous frame
+    out.push_str("\x1b[J");
+    out
+}
+
+impl DisplayBackend for TerminalBackend {
+    delegate_to_framebuffer!(framebuffer);
+
+    fn present(&mut self) {
+        if let Some(last_draw) = self.last_draw {
+            if last_draw.elapsed() < self.refresh_interval {
+                return;
+            }
+        }
+        self.last_draw = Some(Instant::now());
+
+        let text = frame_to_ansi(&self.framebuffer.frame());
+        let mut stdout = io::stdout().lock();
+        if stdout.write_all(text.as_bytes()).and_then(|_| stdout.flush()).is_err() {
+            log!("cannot write the frame to the terminal");
+        }
+    }
+}
+
+impl Drop for TerminalBackend {
+    fn drop(&mut self) {
+        println!("\x1b[0m\x1b[?25h");
+    }
+}
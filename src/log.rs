@@ -0,0 +1,64 @@
+// Runtime messages. They are written to stderr, except while the terminal
+// preview draws on the same terminal: they are then appended to a file so
+// they do not tear the frames.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+// the file the messages go to instead of stderr
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+// like eprintln!, through the redirection
+macro_rules! log {
+    ($($arg:tt)*) => { $crate::log::write(format_args!($($arg)*)) };
+}
+
+pub fn write(args: fmt::Arguments) {
+    let mut file = FILE.lock().unwrap_or_else(|e| e.into_inner());
+    match file.as_mut() {
+        Some(file) => { let _ = writeln!(file, "{args}"); }
+        None => { eprintln!("{args}"); }
+    }
+}
+
+// the messages go to stderr again when it is dropped
+pub struct Redirect;
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        *FILE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+// appends the messages to `path` until the Redirect is dropped
+pub fn redirect(path: &Path) -> Result<Redirect, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {e}", dir.display()))?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)
+        .map_err(|e| format!("cannot open {}: {e}", path.display()))?;
+    *FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    Ok(Redirect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirected_messages_go_to_the_file() {
+        let path = std::env::temp_dir().join(format!("led-display-log-{}", std::process::id())).join("board.log");
+        let redirect = redirect(&path).unwrap();
+        log!("quota: {} requests already used today", 3);
+        drop(redirect);
+        log!("back on stderr");
+        // the other tests may log meanwhile
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("quota: 3 requests already used today\n"));
+        assert!(!text.contains("back on stderr"));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,55 +1,61 @@
-extern crate sdl2;
-
-use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
 use std::future::IntoFuture;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
 use std::path::Path;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::{TextureQuery, WindowCanvas};
 use std::thread;
-// use sdl2::sys::SDL_EventType;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::io::{self, Write};
-use std::os::raw::c_void;
-use std::string::ToString;
-use serde::{Deserialize, Serialize};
-use image::RgbaImage;
-use image::imageops::FilterType;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::task::JoinHandle;
 use chrono::{Local, Datelike, Timelike};
-use sdl2::EventPump;
-use sdl2::surface::Surface;
-use sdl2::ttf::Font;
-
 
- mod bindings {
-    // println!("OUT_DIR is: {}", env::var("OUT_DIR").unwrap());
-
-    #[allow(non_upper_case_globals)]
-    #[allow(non_camel_case_types)]
-    #[allow(non_snake_case)]
-    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
-}
 
+// first, the log! macro is used by the other modules
+#[macro_use]
+mod log;
+mod clock;
+mod config;
+mod display;
+mod matrix;
+mod quota;
+mod reload;
+mod scheduler;
+mod transit;
+mod web;
+
+use config::{BackendKind, Config, EntryConfig, JourneyFormat, ProviderKind};
+use display::DisplayBackend;
+use display::bdf::BdfFont;
+use display::file::FileBackend;
+use display::mirror::MirrorBackend;
+#[cfg(feature = "rpi")]
+use display::rgbmatrix::RgbMatrixBackend;
+#[cfg(feature = "sdl")]
+use display::sdl::SdlBackend;
+use display::terminal::TerminalBackend;
+use quota::{ActiveHours, QuotaPlan};
+use scheduler::PageScheduler;
+use transit::{Answer, Departure, DepartureStatus, FetchError, Sources, TransitProvider};
+use web::{EntryStatus, StatusReport};
+
+// default values, can be overridden by the [settings] table of the config file
 const FPS: u32 = 30;
 const STEP: u32 = 1;
-const FRAME_TIME: Duration = Duration::from_micros((1_000_000 / FPS) as u64);
 const SECOND_NUM_WAIT: u32 = 5;
 
-const SCALE_FACTOR: u32 = 1;
-static SCREEN_WIDTH: u32 = 64 * 3 * SCALE_FACTOR;
-static SCREEN_HEIGHT: u32 = 64 * SCALE_FACTOR;
-// const CUSTOM_EVENT_TYPE: u32 = SDL_EventType::SDL_USEREVENT as u32 + 1;
-const LINE_HEIGHT: i32 = 16 * SCALE_FACTOR as i32;
+const LINE_HEIGHT: i32 = 16;
+// platform announced for another track than planned
+const PLATFORM_CHANGE_COLOR: (u8, u8, u8) = (255, 80, 0);
+// countdown to leaving home for the departure to aim for
+const LEAVE_COLOR: (u8, u8, u8) = (0, 255, 120);
 
-const TOTAL_CHAR_WIDTH: usize = 32;
 // You might want to adjust this value
 // 10 minutes
 const REFRESH_INFERVAL: Duration = Duration::from_secs(60 * 10);
 
+// sent by the remote control server and the simulator keyboard
 enum WebEvent {
     BrightnessUp,
     BrightnessDown,
@@ -59,101 +65,70 @@ enum WebEvent {
     TogglePlay,
 }
 
+const BRIGHTNESS_STEP: u8 = 10;
+
 struct DisplayStatus {
     brightness_level: u8,
     is_playing: bool,
-    dbl: Option<RefCell<DashBoardBusLine>>,
 }
 
 
 impl DisplayStatus {
-    // fn new() ->DisplayStatus{
-    //     DisplayStatus{
-    //         brightness_level:10,
-    //         is_playing:true
-    //     }
-    //
-    // }
+    fn new(brightness_level: u8) -> DisplayStatus {
+        DisplayStatus {
+            brightness_level,
+            is_playing: true,
+        }
+    }
 
     fn increase_light(&mut self) {
-
+        self.brightness_level = (self.brightness_level + BRIGHTNESS_STEP).min(100);
     }
-}
-
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Connection {
-    from: Departure,
-    sections: Vec<Section>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Departure {
-    departureTimestamp: Option<u64>,
-    delay: Option<u32>,
-}
-
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Section {
-    journey: Option<Journey>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Journey {
-    category: String,
-    number: String,
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Connections {
-    connections: Vec<Connection>,
-}
-
-// handle the annoying Rect i32
-macro_rules! rect (
-    ($x:expr, $y:expr, $w:expr, $h:expr) => (
-        Rect::new($x as i32, $y as i32, $w as u32, $h as u32)
-    )
-);
-
-trait Printable {
-    fn get_text(&self) -> String;
-    fn get_color(&self) -> [u8; 3];
-    fn update_text_field(&mut self);
+    fn decrease_light(&mut self) {
+        // never fully off, the panel would look broken
+        self.brightness_level = self.brightness_level.saturating_sub(BRIGHTNESS_STEP).max(BRIGHTNESS_STEP);
+    }
 }
 
-// struct CustomEventData {
-//     // Put your custom data fields here
-//     message: String,
-// }
-
-const URL_SBB: &str = "http://www.transport.opendata.ch/v1/connections?";
-
-struct Answer {
-    last_update: SystemTime,
-    result_list: Vec<URLResult>,
-}
 
 fn get_brightness_from_time() -> f32 {
 
-    let now = Local::now();
-    let mut hour : u32 = now.hour();
-    let res :f32 = match hour {
+    let now = clock::local_now();
+    let hour : u32 = now.hour();
+    match hour {
         7  => 0.3,
         8 => 0.5,
         9 => 0.8,
-        x if x >= 10 && x < 21 => 1.0,
+        10..=20 => 1.0,
         21 => 0.7,
         22 => 0.5,
         23 => 0.3,
         _ => 0.05,
-    };
-    return res;
+    }
+}
+
+// "12:03"
+fn clock_time(time: SystemTime) -> String {
+    let local: chrono::DateTime<Local> = time.into();
+    local.format("%H:%M").to_string()
+}
+
+// "4:05", "1:04:05" past an hour
+fn countdown(duration: Duration) -> String {
+    let mut minutes = duration.as_secs() / 60;
+    let seconds = duration.as_secs() % 60;
+    if minutes < 60 {
+        format!("{minutes}:{seconds:02}")
+    } else {
+        let hours = minutes / 60;
+        minutes %= 60;
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    }
 }
 
 fn get_formatted_time() -> String {
-    let now = Local::now();
+    let now = clock::local_now();
 
     let month = now.month();
     let day = now.day();
@@ -177,295 +152,354 @@ fn get_formatted_time() -> String {
         _ => "",
     };
 
-    let formatted_time = format!("{:}-{:02} {}:{:02}:{:02}", month_str, day, hour, minute, second);
-    return formatted_time;
-}
-
-async fn update_request_content(request_content: URLRequest) -> Option<Answer> {
-    let b_sta = &request_content.begin_station;
-    let e_sta = &request_content.end_station;
-    let limit = request_content.limit;
-    let mut ss = format!("{URL_SBB}from={b_sta}&to={e_sta}&limit={limit}");
-    for elm in &request_content.fields {
-        ss += &format!("&fields[]={elm}").as_str();
-    }
-    let resa = reqwest::get(&ss);
-    println!("{}", &ss);
-
-    let future_text;
-    match resa.await {
-        Ok(e) => { future_text = e.text() }
-        Err(_) => {
-            print!("Error sending GET request: {ss}");
-            return None;
-        }
-    }
-
-    let text_to_parse;
-    match future_text.await {
-        Ok(e) => { text_to_parse = e; }
-        Err(_) => {
-            print!("Error getting text from GET request: {ss}");
-            return None;
-        }
-    }
-
-    println!("{}", text_to_parse);
-    let conn: Connections;
-    match serde_json::from_str(text_to_parse.as_str()) {
-        Ok(e) => { conn = e; }
-        Err(e) => {
-            println!("error is {e}");
-            return None;
-        }
-    };
-
-
-    let url_results: Vec<URLResult> = conn.connections.iter()
-        .map(|c| {
-            let mut curr_index = 0;
-            let len = c.sections.len();
-            while curr_index < len {
-                if c.sections[curr_index].journey.is_some() {
-                    break;
-                }
-                curr_index += 1;
-            }
-            let mut name = "err".to_string();
-            if curr_index < len {
-                let jn = match c.sections[curr_index].journey.clone() {
-                    Some(journ) => { journ }
-                    None => {  Journey { category: "missing".to_string(), number: "missing".to_string() } }
-                };
-
-                name = format!("{}{}", jn.category, jn.number);
-            }
-
-            return URLResult::new(c.from.departureTimestamp, c.from.delay,
-                                  name);
-        }
-        )
-        .collect();
-
-    return Option::Some(Answer {
-        result_list: url_results,
-        last_update: SystemTime::now(),
-    });
-}
-
-#[derive(Clone)]
-struct URLRequest {
-    begin_station: String,
-    end_station: String,
-    fields: Vec<String>,
-    limit: u32,
-}
-
-#[derive(Clone)]
-struct URLResult {
-    timestamp: SystemTime,
-    delay: Duration,
-    transport_name: String,
-    error: bool,
-}
-
-impl URLResult {
-    fn new(opt_departure: Option<u64>, opt_delay: Option<u32>, transport_name: String) -> URLResult {
-        let mut delay = match opt_delay {
-            None => { 0 }
-            Some(e) => { e }
-        };
-
-        let duration_since_epoch;
-
-        match opt_departure {
-            Some(d) => { duration_since_epoch = Duration::from_secs(d); }
-            None => {
-                return URLResult {
-                    timestamp: SystemTime::now(),
-                    delay: Duration::from_secs(0),
-                    transport_name,
-                    error: true,
-                };
-            }
-        };
-
-        let instant = UNIX_EPOCH + duration_since_epoch;
-        URLResult {
-            timestamp: instant,
-            delay: Duration::from_secs(delay as u64 * 60),
-            transport_name,
-            error: false,
-        }
-    }
-}
-
-// from=Lausanne&to=Gen%C3%A8ve&fields[]=connections/from/departure&fields[]=connections/from/delay&limit=5
-struct DashBoardLine {
-    line: String,
-    color: [u8; 3],
+    format!("{:}-{:02} {}:{:02}:{:02}", month_str, day, hour, minute, second)
 }
 
 struct DashBoardBusLine {
-    request_content: URLRequest,
-    result_list: Vec<URLResult>,
-    lines: Vec<String>,
+    provider: Arc<dyn TransitProvider>,
+    result_list: Vec<Departure>,
+    lines: Vec<RichText>,
     basename: String,
     last_update: SystemTime,
-    future_answer: Option<JoinHandle<Option<Answer>>>,
+    future_answer: Option<JoinHandle<Result<Answer, FetchError>>>,
+    // why the last fetch failed, kept until a fetch succeeds
+    last_error: Option<FetchError>,
+    // failed fetches in a row, the retries back off with them
+    failures: u32,
+    retry_at: Option<SystemTime>,
+    // when the last fetch was sent, the retries of the API entries wait for their quota share from it
+    last_attempt: SystemTime,
     color: (u8, u8, u8),
+    refresh_interval: Duration,
+    // characters fitting on one line of the canvas
+    line_chars: usize,
+    journey: JourneyFormat,
+    // the API entries share the daily quota, the gtfs ones are free
+    uses_quota: bool,
+    // the API request of the entry, the entries with the same one are refreshed together
+    shared_request: Option<String>,
+    priority: u32,
+    active_hours: ActiveHours,
+    // the departures leaving sooner than `run_time` cannot be caught
+    walk_time: Duration,
+    run_time: Duration,
+    // departures per line and headsign row, None for the time ordered grid
+    group_size: Option<usize>,
 }
 
 
 impl DashBoardBusLine {
-    fn new(begin_station: String, end_station: String, base_name: String, color: (u8, u8, u8)) -> DashBoardBusLine {
-        DashBoardBusLine {
-            request_content: URLRequest {
-                begin_station,
-                end_station,
-                fields: vec![
-                    "connections/from/departureTimestamp".to_string(),
-                    "connections/from/delay".to_string(),
-                    "connections/sections/journey/category".to_string(),
-                    "connections/sections/journey/number".to_string(),
-                ],
-
-                limit: 10,
-            },
+    fn new(entry: &EntryConfig, sources: &Sources, refresh_interval: Duration, line_chars: usize) -> DashBoardBusLine {
+        let [r, g, b] = entry.color;
+        let provider = transit::create_provider(entry, sources);
+        let now = clock::now();
+        let saved = sources.answers().and_then(|answers| answers.load(&provider.query_key(), now));
+        let mut line = DashBoardBusLine {
+            provider: provider.clone(),
             result_list: vec![],
             lines: vec![],
-            basename: add_N_padding_or_cut(base_name, TOTAL_CHAR_WIDTH),
+            basename: add_n_padding_or_cut(entry.label.clone(), line_chars),
 
-            last_update:
-            SystemTime::now() - Duration::from_secs(3600)
-            ,
+            // never fetched: due at once, whatever the quota plan says
+            last_update: UNIX_EPOCH,
             future_answer: None,
-            color: color,
+            last_error: None,
+            failures: 0,
+            retry_at: None,
+            last_attempt: UNIX_EPOCH,
+            color: (r, g, b),
+            refresh_interval,
+            line_chars,
+            journey: entry.journey,
+            // a gtfs entry with an API fallback spends requests too
+            uses_quota: [Some(entry.provider), entry.fallback].iter().flatten().any(|kind| *kind != ProviderKind::Gtfs),
+            shared_request: provider.shared_request(),
+            priority: entry.priority,
+            // checked when the config is validated
+            active_hours: ActiveHours::parse(&entry.active_hours).unwrap_or_default(),
+            walk_time: Duration::from_secs(entry.walk_time),
+            run_time: Duration::from_secs(entry.run_time.unwrap_or(entry.walk_time)),
+            group_size: entry.group_by_line.then_some(entry.group_size as usize),
+        };
+        if let Some(answ) = saved {
+            // shown until the first answer, which is asked for at once
+            line.last_update = answ.last_update;
+            line.result_list = answ.departures;
+            line.retry_at = Some(now);
+        }
+        line
+    }
+
+    // the API entries wait for their active hours and for their share of the quota,
+    // a retry after a failure too: it spends a request like any refresh
+    fn refresh_due(&self, plan: Option<&QuotaPlan>, now: SystemTime) -> bool {
+        let (interval, planned) = match plan {
+            Some(plan) if self.uses_quota => {
+                if !self.active_hours.contains(now) {
+                    return false;
+                }
+                match plan.interval(self.priority, self.refresh_interval) {
+                    Some(e) => { (e, true) }
+                    None => { return false; }
+                }
+            }
+            _ => { (self.refresh_interval, false) }
+        };
+        match self.retry_at {
+            Some(retry_at) if planned => { retry_at.max(self.last_attempt + interval) <= now }
+            Some(retry_at) => { retry_at <= now }
+            None => { self.last_update + interval <= now }
         }
     }
 
-    // fn get_text(&self) -> Vec<String> {
-    //     let mut res = Vec::new();
-    //     res.push(self.basename.clone());
-    //     for elm in &self.lines {
-    //         res.push(elm.clone());
-    //     }
-    //     return res;
-    // }
+    // keep the fetched departures of `old` when both entries make the same request,
+    // so a config reload does not cost any API call
+    fn take_results_from(&mut self, old: DashBoardBusLine) {
+        self.result_list = old.result_list;
+        self.lines = old.lines;
+        self.last_update = old.last_update;
+        self.future_answer = old.future_answer;
+        self.last_error = old.last_error;
+        self.failures = old.failures;
+        self.retry_at = old.retry_at;
+        self.last_attempt = old.last_attempt;
+    }
 
     fn get_color(&self) -> (u8, u8, u8) {
-        self.color.clone()
+        self.color
     }
 
-    fn make_line_info(&self, index: usize, now: SystemTime) -> String {
+    // with `aim` the countdown is the time left before walking to the stop
+    fn make_line_info(&self, index: usize, now: SystemTime, aim: bool) -> RichText {
         let bn = "err";
 
         let res_list_copy = self.result_list.clone();
 
         if index >= res_list_copy.len() {
-            return format!("{bn}: end reached update req!");
+            return RichText::plain(format!("{bn}: end reached update req!"));
         }
 
         let current_rr_res = &res_list_copy.get(index);
         if current_rr_res.is_none() {
-            return format!("{bn}: out of bounds access!");
+            return RichText::plain(format!("{bn}: out of bounds access!"));
         }
         let current_rr = current_rr_res.unwrap();
-        let ts = current_rr.timestamp + current_rr.delay;
+        let ts = current_rr.time();
         let diff_dur_res = ts.duration_since(now);
         if diff_dur_res.is_err() {
-            return format!("{bn}: invalid time!");
+            return RichText::plain(format!("{bn}: invalid time!"));
         }
         let diff_dur = diff_dur_res.unwrap();
-        let mut minutes = diff_dur.as_secs() / 60;
-        let seconds = diff_dur.as_secs() % 60;
-        let name = &current_rr.transport_name;
-        let mut acc: String;
-        if minutes < 60 {
-            acc = format!("{name} {minutes}:{seconds:02}");
+        let name = current_rr.journey_name();
+        if current_rr.status == DepartureStatus::Cancelled {
+            return RichText::plain(format!("{name} cancelled"));
+        }
+        let mut line = RichText::plain(format!("{name} "));
+        if aim {
+            match diff_dur.checked_sub(self.walk_time) {
+                Some(leave_in) => { line.push_colored(&countdown(leave_in), LEAVE_COLOR); }
+                // only caught when running
+                None => { line.push_colored("run!", LEAVE_COLOR); }
+            }
         } else {
-            let hours = minutes / 60;
-            minutes %= 60;
-            acc = format!("{name} {hours}:{minutes:02}:{seconds:02}");
+            line.push_str(&countdown(diff_dur));
         }
 
-        let delay = current_rr.delay.as_secs() / 60;
+        let delay = current_rr.delay().as_secs() / 60;
         if delay != 0 {
-            acc += format!("(+{delay})").as_str();
+            line.push_str(&format!("(+{delay})"));
+        }
+        if let Some(platform) = &current_rr.platform {
+            line.push_str(" ");
+            if current_rr.platform_changed {
+                line.push_colored(platform, PLATFORM_CHANGE_COLOR);
+            } else {
+                line.push_str(platform);
+            }
+        }
+        // the headsign of a plain departure, cut with the rest of the cell;
+        // a connection goes to the destination of the entry
+        if current_rr.legs.is_empty() && !current_rr.destination.is_empty() {
+            line.push_str(&format!(" {}", current_rr.destination));
         }
-        return acc;
+        line
     }
 
-    async fn update_text_field(&mut self) {
+    // the departure line with arrival and travel time, then the legs and the changes between them
+    fn make_journey_lines(&self, index: usize, now: SystemTime, aim: bool) -> Vec<RichText> {
+        let departure = &self.result_list[index];
+        let mut summary = self.make_line_info(index, now, aim);
+        if let Some(arrival) = departure.arrival {
+            summary.push_str(&format!(" arr {}", clock_time(arrival)));
+        }
+        if let Some(travel) = departure.travel_time() {
+            summary.push_str(&format!(" {}'", travel.as_secs() / 60));
+        }
+        let mut lines = vec![summary];
+        for leg in &departure.legs {
+            if let Some(transfer) = leg.transfer {
+                lines.push(RichText::plain(format!(" change {}' at {}", transfer.as_secs() / 60, leg.from)));
+            }
+            let time = leg.departure.map(clock_time).unwrap_or_default();
+            // the platform goes first, long station names are cut
+            let platform = match &leg.departure_platform {
+                Some(p) => { format!(" pl.{p}") }
+                None => { String::new() }
+            };
+            lines.push(RichText::plain(format!(" {time} {}{platform} {}", leg.name(), leg.from)));
+        }
+        lines
+    }
+
+    // retry sooner after a transient error, the next regular refresh otherwise
+    fn fetch_failed(&mut self, e: FetchError) {
+        self.failures += 1;
+        let delay = e.backoff(self.failures, self.refresh_interval).unwrap_or(self.refresh_interval);
+        log!("{}: {e}, retry in {}s", self.basename.trim_end(), delay.as_secs());
+        if e == FetchError::Empty {
+            // nothing is running any more, the old departures would stay on screen
+            self.result_list.clear();
+        }
+        self.retry_at = Some(clock::now() + delay);
+        self.last_error = Some(e);
+    }
+
+    // "T2 Zurich, Farbhof    3' 10' 17'", the rows ordered by their next departure;
+    // the departure to aim for shows the minutes left before leaving
+    fn make_group_lines(&self, index: usize, now: SystemTime, aim: Option<usize>, group_size: usize) -> Vec<RichText> {
+        let mut groups: Vec<(String, String, Vec<usize>)> = vec![];
+        for (i, departure) in self.result_list.iter().enumerate().skip(index) {
+            if departure.status == DepartureStatus::Cancelled {
+                continue;
+            }
+            let name = departure.journey_name();
+            match groups.iter_mut().find(|(n, d, _)| *n == name && *d == departure.destination) {
+                Some((_, _, found)) => {
+                    if found.len() < group_size {
+                        found.push(i);
+                    }
+                }
+                None => { groups.push((name, departure.destination.clone(), vec![i])); }
+            }
+        }
+        groups.into_iter().map(|(name, destination, found)| {
+            let mut times = RichText::default();
+            for i in found {
+                let left = self.result_list[i].time().duration_since(now).unwrap_or_default();
+                times.push_str(" ");
+                if aim == Some(i) {
+                    let leave = match left.checked_sub(self.walk_time) {
+                        Some(e) => { format!("{}'", e.as_secs() / 60) }
+                        None => { "run!".to_string() }
+                    };
+                    times.push_colored(&leave, LEAVE_COLOR);
+                } else {
+                    times.push_str(&format!("{}'", left.as_secs() / 60));
+                }
+            }
+            let width = self.line_chars.saturating_sub(times.text.chars().count());
+            let mut line = RichText::plain(add_n_padding_or_cut(format!("{name} {destination}"), width));
+            line.append(times);
+            line
+        }).collect()
+    }
+
+    // `due_requests` are refreshed whether the entry is due or not, another entry sends them
+    async fn update_text_field(&mut self, sources: &Sources, plan: Option<&QuotaPlan>, due_requests: &[String]) {
         self.lines.clear();
-        let copyyy = self.request_content.clone();
-        let last_upp = self.last_update.clone();
         if self.future_answer.is_none() {
-            if last_upp + REFRESH_INFERVAL > SystemTime::now() {} else {
-                let future = update_request_content(copyyy);
-                // *self.last_update = SystemTime::now();
+            let joins_request = self.shared_request.as_ref().is_some_and(|r| due_requests.contains(r));
+            let now = clock::now();
+            if joins_request || self.refresh_due(plan, now) {
+                self.last_attempt = now;
+                let future = self.provider.fetch();
                 self.future_answer = Some(tokio::spawn(future));
-                // self.future_answer.unwrap().into_future().await;
-                println!("spawn future ");
             }
         } else if let Some(future_handle) = &mut self.future_answer {
             if future_handle.is_finished() {
-                println!("future_handle");
-                // Run the future to completion
-
-                let option_answ = future_handle.into_future().await.unwrap();
-                match option_answ {
-                    Some(answ) => {
+                let result_answ = future_handle.into_future().await.unwrap();
+                match result_answ {
+                    Ok(answ) => {
+                        if let Some(answers) = sources.answers() {
+                            answers.save(&self.provider.query_key(), &answ);
+                        }
                         self.last_update = answ.last_update;
-                        self.result_list = answ.result_list;
+                        self.result_list = answ.departures;
+                        self.last_error = None;
+                        self.failures = 0;
+                        self.retry_at = None;
                     }
-                    _ => {}
+                    Err(e) => { self.fetch_failed(e); }
                 }
-                // self.last_update = result_answ.unwrap().;
                 self.future_answer = None;
             }
         }
+        // the predictions move more often than the entry is fetched
+        sources.apply_realtime(&mut self.result_list);
 
         let res_list_copy = self.result_list.clone();
-        let now = SystemTime::now();
+        let now = clock::now();
         let mut index = 0;
         for rr in &res_list_copy {
-            if rr.error {
-                index += 1;
-                continue;
-            }
-            let is_delay_passed = now > rr.timestamp + rr.delay;
+            // gone, or leaving before we can reach the stop
+            let is_delay_passed = now + self.run_time > rr.time();
 
             if !is_delay_passed {
                 break;
             }
             index += 1;
         }
+        // the first departure we can still catch
+        let aim = if self.walk_time.is_zero() {
+            None
+        } else {
+            (index..res_list_copy.len()).find(|&i| res_list_copy[i].status != DepartureStatus::Cancelled)
+        };
 
         let len_res_list = res_list_copy.len();
 
 
         if len_res_list == 0 {
-            self.lines.push(format!("No information available!"));
-            self.lines.push(format!("Check internet connection!"));
+            self.lines.push(RichText::plain("No information available!".to_string()));
+            match &self.last_error {
+                Some(e) => { self.lines.push(RichText::plain(e.to_string()).padded_or_cut(self.line_chars)); }
+                None => { self.lines.push(RichText::plain("Check internet connection!".to_string())); }
+            }
             return;
         }
 
-        for _i in 0..2 {
-            let mut text_acc: String = "".to_string();
-            for _j in 0..2 {
-                while index < len_res_list {
-                    if !res_list_copy[index].error {
-                        break;
-                    }
-                    index += 1;
+        if self.journey == JourneyFormat::Detailed {
+            if index >= len_res_list {
+                self.lines.push(self.make_line_info(index, now, false));
+            }
+            // the next two connections, the entry scrolls through all their lines
+            for i in index..(index + 2).min(len_res_list) {
+                for line in self.make_journey_lines(i, now, aim == Some(i)) {
+                    self.lines.push(line.padded_or_cut(self.line_chars));
                 }
+            }
+            return;
+        }
 
-                let text1 = self.make_line_info(index, now);
+        if let Some(group_size) = self.group_size {
+            let lines = self.make_group_lines(index, now, aim, group_size);
+            if lines.is_empty() {
+                self.lines.push(self.make_line_info(index, now, false));
+            }
+            for line in lines {
+                self.lines.push(line.padded_or_cut(self.line_chars));
+            }
+            return;
+        }
+
+        for _i in 0..2 {
+            let mut text_acc = RichText::default();
+            for _j in 0..2 {
+                let text1 = self.make_line_info(index, now, aim == Some(index));
 
-                let text2 = add_N_padding_or_cut(text1, TOTAL_CHAR_WIDTH / 2);
+                let text2 = text1.padded_or_cut(self.line_chars / 2);
 
-                text_acc += text2.as_str();
+                text_acc.append(text2);
                 index += 1;
             }
             self.lines.push(text_acc);
@@ -473,32 +507,21 @@ impl DashBoardBusLine {
     }
 }
 
-fn add_N_padding_or_cut(text1: String, max_car_num: usize) -> String {
-    let text_res;
-    if text1.len() > max_car_num {
-        let first_20 = &text1[0..max_car_num];
-        text_res = first_20.to_string();
+// counts characters, not bytes: station names are full of umlauts
+fn add_n_padding_or_cut(text1: String, max_car_num: usize) -> String {
+    let char_num = text1.chars().count();
+    if char_num > max_car_num {
+        text1.chars().take(max_car_num).collect()
     } else {
-        let padding = max_car_num - text1.len();
+        let padding = max_car_num - char_num;
         let supp_text = " ".repeat(padding);
-        text_res = text1 + supp_text.as_str();
-    }
-    text_res
-}
-
-impl Printable for DashBoardLine {
-    fn get_text(&self) -> String {
-        self.line.clone()
-    }
-    fn get_color(&self) -> [u8; 3] {
-        self.color.clone()
+        text1 + supp_text.as_str()
     }
-    fn update_text_field(&mut self) {}
 }
 
 
 struct DashBoardPage {
-    sbb_entry: Vec<Box<DashBoardBusLine>>,
+    sbb_entry: Vec<DashBoardBusLine>,
     current_index: usize,
 }
 
@@ -507,10 +530,9 @@ impl DashBoardPage {
         DashBoardPage { sbb_entry: vec![], current_index: 0 }
     }
 
-    fn add_sbb_entry(&mut self, base_name: String, begin: String, end: String,
-                     color: (u8, u8, u8)) {
-        let line = DashBoardBusLine::new(begin, end, base_name, color);
-        self.sbb_entry.push(Box::new(line));
+    fn add_sbb_entry(&mut self, entry: &EntryConfig, sources: &Sources, refresh_interval: Duration, line_chars: usize) {
+        let line = DashBoardBusLine::new(entry, sources, refresh_interval, line_chars);
+        self.sbb_entry.push(line);
     }
 
     fn move_to_next_sbb_entry(&mut self) {
@@ -518,55 +540,196 @@ impl DashBoardPage {
         self.current_index = index;
     }
 
+    fn move_to_prev_sbb_entry(&mut self) {
+        let len = self.sbb_entry.len();
+        self.current_index = (self.current_index + len - 1) % len;
+    }
+
     fn get_current_size(&self) -> usize {
         let index = self.current_index;
-        return self.sbb_entry[index].lines.len() + 2;
+        self.sbb_entry[index].lines.len() + 2
     }
 
     fn get_current_entry(&self) -> &DashBoardBusLine {
         let index = self.current_index;
-        return &self.sbb_entry[index];
+        &self.sbb_entry[index]
     }
 
     fn get_next_entry(&self) -> &DashBoardBusLine {
         let index = (self.current_index + 1) % self.sbb_entry.len();
-        return &self.sbb_entry[index];
+        &self.sbb_entry[index]
     }
 }
 
 struct DashBoard {
     pages: Vec<DashBoardPage>,
     curr_page: usize,
+    scheduler: PageScheduler,
+    line_chars: usize,
+    sources: Sources,
 }
 
-struct DisplayLineData {
+// text of a line, some character ranges drawn in their own color
+#[derive(Clone, Default)]
+struct RichText {
     text: String,
+    highlights: Vec<(Range<usize>, (u8, u8, u8))>,
+}
+
+impl RichText {
+    fn plain(text: String) -> RichText {
+        RichText { text, highlights: vec![] }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        self.text += text;
+    }
+
+    fn push_colored(&mut self, text: &str, color: (u8, u8, u8)) {
+        let start = self.text.chars().count();
+        self.text += text;
+        self.highlights.push((start..start + text.chars().count(), color));
+    }
+
+    fn append(&mut self, other: RichText) {
+        let offset = self.text.chars().count();
+        self.text += other.text.as_str();
+        self.highlights.extend(other.highlights.into_iter()
+            .map(|(range, color)| (range.start + offset..range.end + offset, color)));
+    }
+
+    fn padded_or_cut(self, max_car_num: usize) -> RichText {
+        RichText {
+            text: add_n_padding_or_cut(self.text, max_car_num),
+            highlights: self.highlights.into_iter()
+                .filter(|(range, _)| range.start < max_car_num)
+                .map(|(range, color)| (range.start..range.end.min(max_car_num), color))
+                .collect(),
+        }
+    }
+
+    // consecutive pieces of text sharing a color, `color` outside the highlights
+    fn runs(&self, color: (u8, u8, u8)) -> Vec<(String, (u8, u8, u8))> {
+        let mut runs: Vec<(String, (u8, u8, u8))> = vec![];
+        for (i, c) in self.text.chars().enumerate() {
+            let char_color = self.highlights.iter()
+                .find(|(range, _)| range.contains(&i))
+                .map_or(color, |(_, highlight)| *highlight);
+            match runs.last_mut() {
+                Some((text, last)) if *last == char_color => { text.push(c); }
+                _ => { runs.push((c.to_string(), char_color)); }
+            }
+        }
+        runs
+    }
+}
+
+struct DisplayLineData {
+    text: RichText,
     color: (u8, u8, u8),
 }
 
 impl DisplayLineData {
     fn new(name: String, color: (u8, u8, u8)) -> DisplayLineData {
-        DisplayLineData { text: name, color }
+        DisplayLineData { text: RichText::plain(name), color }
+    }
+
+    fn rich(text: RichText, color: (u8, u8, u8)) -> DisplayLineData {
+        DisplayLineData { text, color }
     }
 }
 
 impl DashBoard {
-    fn new() -> DashBoard {
+    fn new(scheduler: PageScheduler, line_chars: usize, sources: Sources) -> DashBoard {
         DashBoard {
             pages: vec![],
             curr_page: 0,
+            scheduler,
+            line_chars,
+            sources,
+        }
+    }
+
+    fn from_config(config: &Config, line_chars: usize, sources: Sources) -> DashBoard {
+        let mut dashboard = DashBoard::new(PageScheduler::from_config(config), line_chars, sources);
+        let refresh_interval = config.settings.refresh_interval();
+        for page_config in &config.pages {
+            let mut page = DashBoardPage::new();
+            for entry in &page_config.entries {
+                page.add_sbb_entry(entry, &dashboard.sources, refresh_interval, line_chars);
+            }
+            dashboard.add_page(page);
         }
+        dashboard
     }
 
     fn add_page(&mut self, p: DashBoardPage) {
         self.pages.push(p);
     }
 
+    // rebuild the pages from a new config, reusing the entries whose request did not change
+    fn reload(&mut self, config: &Config, sources: Sources) {
+        let old_indexes: Vec<usize> = self.pages.iter().map(|page| page.current_index).collect();
+        let mut old_entries: Vec<DashBoardBusLine> = self.pages.drain(..)
+            .flat_map(|page| page.sbb_entry)
+            .collect();
+        let mut new_dashboard = DashBoard::from_config(config, self.line_chars, sources);
+        let mut reused = 0;
+        for (i, page) in new_dashboard.pages.iter_mut().enumerate() {
+            if let Some(&index) = old_indexes.get(i) {
+                if index < page.sbb_entry.len() {
+                    page.current_index = index;
+                }
+            }
+            for entry in &mut page.sbb_entry {
+                let old_pos = old_entries.iter()
+                    .position(|old| old.provider.query_key() == entry.provider.query_key());
+                if let Some(pos) = old_pos {
+                    entry.take_results_from(old_entries.swap_remove(pos));
+                    reused += 1;
+                }
+            }
+        }
+        log!("config reloaded: {} page(s), {reused} entries kept their data", new_dashboard.pages.len());
+
+        self.pages = new_dashboard.pages;
+        self.scheduler = new_dashboard.scheduler;
+        self.sources = new_dashboard.sources;
+        if self.curr_page >= self.pages.len() {
+            self.curr_page = 0;
+        }
+        self.scheduler.show(self.curr_page, SystemTime::now());
+    }
+
+    // hidden pages are refreshed too, so they have data when they come on screen
     async fn update_content(&mut self) {
-        let page = &mut self.pages[self.curr_page];
-        let lines = &mut page.sbb_entry;
-        for elm in lines {
-            elm.update_text_field().await;
+        let now = SystemTime::now();
+        self.scheduler.plan(now);
+        // the active hours follow the board clock
+        let board_now = clock::now();
+        let plan = self.sources.quota().map(|quota| {
+            // the entries sharing a request cost one request per refresh
+            let mut groups = vec![];
+            let mut shared: HashMap<&String, Vec<(u32, &ActiveHours)>> = HashMap::new();
+            for elm in self.pages.iter().flat_map(|page| &page.sbb_entry).filter(|elm| elm.uses_quota) {
+                match &elm.shared_request {
+                    Some(request) => { shared.entry(request).or_default().push((elm.priority, &elm.active_hours)); }
+                    None => { groups.push(vec![(elm.priority, &elm.active_hours)]); }
+                }
+            }
+            groups.extend(shared.into_values());
+            quota.plan(groups.into_iter(), board_now)
+        });
+        // an entry due takes the others of its request along, so they share it
+        let due_requests: Vec<String> = self.pages.iter()
+            .flat_map(|page| &page.sbb_entry)
+            .filter(|elm| elm.future_answer.is_none() && elm.refresh_due(plan.as_ref(), board_now))
+            .filter_map(|elm| elm.shared_request.clone())
+            .collect();
+        for page in &mut self.pages {
+            for elm in &mut page.sbb_entry {
+                elm.update_text_field(&self.sources, plan.as_ref(), &due_requests).await;
+            }
         }
     }
 
@@ -580,7 +743,7 @@ impl DashBoard {
             return vec;
         }
         let page: &DashBoardPage = &self.pages[self.curr_page];
-        let ftime = add_N_padding_or_cut(format!("[{}]", get_formatted_time()), TOTAL_CHAR_WIDTH);
+        let ftime = add_n_padding_or_cut(format!("[{}]", get_formatted_time()), self.line_chars);
         vec.push(DisplayLineData::new(
             ftime.clone(),
             (255, 150, 255),
@@ -591,7 +754,7 @@ impl DashBoard {
             sbb_entry_1.get_color(),
         ));
         for elm in &sbb_entry_1.lines {
-            vec.push(DisplayLineData::new(
+            vec.push(DisplayLineData::rich(
                 elm.clone(),
                 sbb_entry_1.get_color(),
             ));
@@ -601,373 +764,295 @@ impl DashBoard {
             (255, 150, 255),
         ));
 
-        let sbb_entry_2 = page.get_next_entry();
+        // the entry scrolling in comes from the next page when the page is about to change
+        let next_page = self.scheduler.planned();
+        let sbb_entry_2 = if next_page != self.curr_page && next_page < self.pages.len() {
+            self.pages[next_page].get_current_entry()
+        } else {
+            page.get_next_entry()
+        };
         vec.push(DisplayLineData::new(
             sbb_entry_2.basename.clone(),
             sbb_entry_2.get_color(),
         ));
         for elm in &sbb_entry_2.lines {
-            vec.push(DisplayLineData::new(
+            vec.push(DisplayLineData::rich(
                 elm.clone(),
                 sbb_entry_2.get_color(),
             ));
         }
 
-        return vec;
+        vec
+    }
+
+    fn current_label(&self) -> String {
+        match self.pages.get(self.curr_page) {
+            Some(page) => { page.get_current_entry().basename.trim_end().to_string() }
+            None => { String::new() }
+        }
+    }
+
+    fn entry_statuses(&self) -> Vec<EntryStatus> {
+        self.pages.iter()
+            .flat_map(|page| &page.sbb_entry)
+            .map(|entry| EntryStatus {
+                label: entry.basename.trim_end().to_string(),
+                last_update: entry.last_update.duration_since(UNIX_EPOCH).ok()
+                    .filter(|since| !since.is_zero())
+                    .map(|since| since.as_secs()),
+                error: entry.last_error.as_ref().map(|e| e.to_string()),
+            })
+            .collect()
     }
 
     fn get_curr_page_size(&self) -> usize {
         let page: &DashBoardPage = &self.pages[self.curr_page];
-        return page.get_current_size();
+        page.get_current_size()
     }
 
-    fn move_next_page_element(&mut self) {
-        let page = &mut self.pages[self.curr_page];
-        return page.move_to_next_sbb_entry();
+    // manual navigation, the page scheduler is restarted on the current page
+    fn show_next_entry(&mut self) {
+        self.pages[self.curr_page].move_to_next_sbb_entry();
+        self.scheduler.show(self.curr_page, SystemTime::now());
     }
-}
 
-// Scale fonts to a reasonable size when they're too big (though they might look less smooth)
-fn get_centered_rect(rect_width: u32, rect_height: u32, cons_width: u32, cons_height: u32) -> Rect {
-    let wr = rect_width as f32 / cons_width as f32;
-    let hr = rect_height as f32 / cons_height as f32;
+    fn show_prev_entry(&mut self) {
+        self.pages[self.curr_page].move_to_prev_sbb_entry();
+        self.scheduler.show(self.curr_page, SystemTime::now());
+    }
 
-    let (w, h) = if wr > 1f32 || hr > 1f32 {
-        if wr > hr {
-            println!("Scaling down! The text will look worse!");
-            let h = (rect_height as f32 / wr) as i32;
-            (cons_width as i32, h)
-        } else {
-            println!("Scaling down! The text will look worse!");
-            let w = (rect_width as f32 / hr) as i32;
-            (w, cons_height as i32)
+    // back to the first entry of the first page
+    fn reset(&mut self) {
+        for page in &mut self.pages {
+            page.current_index = 0;
         }
-    } else {
-        (rect_width as i32, rect_height as i32)
-    };
+        self.curr_page = 0;
+        self.scheduler.show(0, SystemTime::now());
+    }
 
-    let cx = (SCREEN_WIDTH as i32 - w) / 2;
-    let cy = (SCREEN_HEIGHT as i32 - h) / 2;
-    rect!(cx, cy, w, h)
+    fn move_next_page_element(&mut self) {
+        let next_page = self.scheduler.on_cycle_end(SystemTime::now());
+        if next_page != self.curr_page && next_page < self.pages.len() {
+            // the page we leave resumes with its following entry next time
+            self.pages[self.curr_page].move_to_next_sbb_entry();
+            self.curr_page = next_page;
+            return;
+        }
+        let page = &mut self.pages[self.curr_page];
+        page.move_to_next_sbb_entry();
+    }
 }
 
+// draws the dashboard lines, `scroll` below 0 moves everything up
+fn render_lines(backend: &mut dyn DisplayBackend, lines: &[DisplayLineData], scroll: i32) {
+    backend.fill((0, 0, 0));
 
-fn printooo(
-    canvas: &mut WindowCanvas,
-    lines: &Vec<DisplayLineData>,
-    font: &sdl2::ttf::Font,
-    co_b: i32,
-) {
-    canvas.clear();
-    let texture_creator = canvas.texture_creator();
-
-
+    let bri = get_brightness_from_time();
+    let change_val = scroll.min(0);
     for (i, line) in lines.iter().enumerate() {
-        // render a surface, and convert it to a texture bound to the canvas
-
-        let (r1, g1, b1) = line.color;
- 
-        println!("R1={}" , r1);
-        println!("G1={}" , g1);
-        println!("B1={}" , b1);
-
-       let bri = get_brightness_from_time();
-        println!("BRI={}" , bri);
-        let r : u8 = (r1 as f32 * bri) as u8;
-        let g : u8 = (g1 as f32 * bri) as u8;
-        let b : u8 = (b1 as f32 * bri) as u8;
-
-        println!("R={}" , r);
-        println!("G={}" , g);
-        println!("B={}" , b);
-
-        let surface;
-        match font
-            .render(line.text.as_str())
-            .blended(Color::RGBA(r, g, b, 255))
-            .map_err(|e| e.to_string())
-        {
-            Ok(e) => { surface = e; }
-            _ => { continue; }
-        }
-
-        let texture;
-        match texture_creator
-            .create_texture_from_surface(&surface)
-            .map_err(|e| e.to_string()) {
-            Ok(e) => { texture = e; }
-            Err(_) => { continue; }
-        }
-
-        let TextureQuery { width, height, .. } = texture.query();
-
-        // If the example text is too big for the screen, downscale it (and center irregardless)
-        let padding = 16;
-        let target = get_centered_rect(
-            width,
-            height,
-            SCREEN_WIDTH - padding,
-            SCREEN_HEIGHT - padding,
-        );
-        let mut change_val = 0;
-        if co_b < 0 {
-            change_val = co_b;
-        }
-        // Offset target rect to the correct line
-        let target = Rect::new(0, 0 + i as i32 * LINE_HEIGHT + change_val, target.width(), target.height());
-
-        let aa = canvas.copy(&texture, None, Some(target));
-        if aa.is_err() {
-            println!("COPY ERROR");
+        // baseline of the line, the font is 16 pixels high
+        let upval: i32 = 10 + LINE_HEIGHT * i as i32 + change_val;
+        if upval < 0 || upval - LINE_HEIGHT > backend.height() as i32 {
+            continue;
         }
-    }
-
-    let pixels;
-    match canvas
-        .read_pixels(None, sdl2::pixels::PixelFormatEnum::RGBA8888) {
-        Ok(e) => { pixels = e; }
-        Err(_) => {
-            println!("cannot read pixels ");
-            return;
+        let mut x = 0;
+        for (text, (r1, g1, b1)) in line.text.runs(line.color) {
+            let r: u8 = (r1 as f32 * bri) as u8;
+            let g: u8 = (g1 as f32 * bri) as u8;
+            let b: u8 = (b1 as f32 * bri) as u8;
+            x = backend.draw_text(x, upval, (r, g, b), &text);
         }
     }
 
-    let image_res =
-        RgbaImage::from_raw(
-            SCREEN_WIDTH as u32,
-            SCREEN_HEIGHT as u32,
-            pixels);
-    if image_res.is_none() {
-        return;
-    }
-    let image = image_res.unwrap();
-    let resized_img = image::imageops::resize(&image, 64 * 3, 64, FilterType::Nearest);
-    resized_img.save(Path::new("output.png")).unwrap();
+    backend.present();
 }
 
+fn create_single_backend(kind: BackendKind, config: &Config, width: u32, height: u32)
+                         -> Result<Box<dyn DisplayBackend>, String> {
+    let font = &config.settings.font;
+    let display = &config.display;
+    let backend: Box<dyn DisplayBackend> = match kind {
+        #[cfg(feature = "rpi")]
+        BackendKind::Rpi => Box::new(RgbMatrixBackend::new(font, &config.matrix)?),
+        #[cfg(not(feature = "rpi"))]
+        BackendKind::Rpi => return Err("the rpi backend needs a build with `--features rpi`".to_string()),
+        #[cfg(feature = "sdl")]
+        BackendKind::Sdl => Box::new(SdlBackend::new(
+            width,
+            height,
+            display.scale,
+            font,
+            display.video_driver.as_deref(),
+        )?),
+        #[cfg(not(feature = "sdl"))]
+        BackendKind::Sdl => return Err("the sdl backend needs a build with `--features sdl`".to_string()),
+        BackendKind::File => Box::new(FileBackend::new(
+            width,
+            height,
+            font,
+            &display.file,
+            config.settings.frame_time(),
+        )?),
+        BackendKind::Terminal => Box::new(TerminalBackend::new(width, height, font, display.terminal.fps)?),
+    };
+    Ok(backend)
+}
 
-fn update(sdl_context: &sdl2::Sdl,
-          indexx: &mut i32,
-          alive: &mut bool) {
-    let mut event_pump;
-    match sdl_context.event_pump() {
-        Ok(e) => { event_pump = e; }
-        Err(_) => {
-            println!("Could not get event pump!");
-            return;
-        }
+fn create_backend(config: &Config) -> Result<Box<dyn DisplayBackend>, String> {
+    let display = &config.display;
+    // the software backends emulate the panels described by [matrix]
+    let (width, height) = config.matrix.canvas_size();
+    let primary = create_single_backend(display.backend, config, width, height)?;
+    if display.mirror.is_empty() {
+        return Ok(primary);
     }
-    while let Some(event) = event_pump.poll_event() {
-        match event {
-            Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => {
-                *alive = false;
-                break;
-            }
-            Event::KeyDown {
-                keycode: Some(Keycode::A),
-                ..
-            } => {
-                if *indexx < 15 {
-                    *indexx = 255;
-                }
-                *indexx -= 10;
-                if *indexx < 15 {
-                    *indexx = 255;
-                }
-            }
-            _ => {}
-        }
+    // mirrors use the size of the primary canvas
+    let (width, height) = (primary.width(), primary.height());
+    let mut mirrors = vec![];
+    for kind in &display.mirror {
+        mirrors.push(create_single_backend(*kind, config, width, height)?);
     }
+    Ok(Box::new(MirrorBackend::new(primary, mirrors)))
 }
 
-async fn run(font_path: &Path) -> Result<(), String> {
-//  let sdl_context = sdl2::init()?;
-//  let video_subsys = sdl_context.video()?;
-//  let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
-
-//  let window = video_subsys
-//      .window("SDL2_TTF Example", SCREEN_WIDTH, SCREEN_HEIGHT)
-//      .position_centered()
-//      .opengl()
-//      .build()
-//      .map_err(|e| e.to_string())?;
-
-//  let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+// number of characters of the font fitting on one line of the canvas
+fn line_chars(font_path: &str, width: u32) -> Result<usize, String> {
+    let font = BdfFont::load(font_path)?;
+    let char_width = font.char_width('0').max(1);
+    Ok((width as i32 / char_width) as usize)
+}
 
-//  // Load a font
-//  let font = ttf_context.load_font(font_path, 160)?;
+async fn run(config_path: &Path, led_flags: &[(String, Option<String>)]) -> Result<(), String> {
+    let mut config = Config::load(config_path)?;
+    for (flag, value) in led_flags {
+        config.matrix.apply_flag(flag, value.as_deref())?;
+    }
+    config.matrix.validate().map_err(|e| format!("matrix.{e}"))?;
+    let settings = &config.settings;
+    let mut frame_time = settings.frame_time();
+    let mut wait_frames = (settings.fps * STEP * settings.second_num_wait) as i32;
+    let mut config_updates = reload::watch_config(config_path.to_path_buf())?;
 
-    let mut indexx = (FPS * STEP * SECOND_NUM_WAIT) as i32;
+    let mut indexx = wait_frames;
     let mut last_frame_time = SystemTime::now();
-    let mut alive = true;
-
-    let mut dbl: DashBoard = DashBoard::new();
-    let mut page: DashBoardPage = DashBoardPage::new();
-
-    page.add_sbb_entry(
-        "Freihofstrasse => HB".to_string(),
-        "Zurich,Freihofstrasse".to_string(),
-        "Zurich,Letzigrund".to_string(),
-        (255, 0, 0),
-    );
-    page.add_sbb_entry(
-        "Siemens => HB".to_string(),
-        "Zurich, Siemens".to_string(),
-        "Zurich, HB".to_string(),
-        (0, 255, 0),
-    );
-    page.add_sbb_entry(
-        "Kappeli => Altstatten".to_string(),
-        "Zurich,Kappeli".to_string(),
-        "Zurich,Letzipark West".to_string(),
-        (0, 255, 255),
-    );
-    page.add_sbb_entry(
-        "Albisrank => Hardbrucke".to_string(),
-        "Zurich,Albisrank".to_string(),
-        "Zurich,Hardbrucke".to_string(),
-        (255, 0, 255),
-    );
-
-    page.add_sbb_entry(
-        "HB => Geneve".to_string(),
-        "Zurich,HB".to_string(),
-        "Geneve".to_string(),
-        (255, 255, 0),
-    );
-
-    dbl.add_page(page);
-
-    let hardware_mapping = std::ffi::CString::new("regular").unwrap();
-    let led_rgb_sequence = std::ffi::CString::new("RGB").unwrap();
-    let pixel_mapper_config = std::ffi::CString::new("").unwrap();
-    let panel_type = std::ffi::CString::new("").unwrap();
-    let mut rgb_option =  bindings::RGBLedMatrixOptions{
-        hardware_mapping: hardware_mapping.as_ptr(),
-        rows: 64,
-        cols: 64,
-        chain_length: 3,
-        parallel: 0,
-        pwm_bits: 0,
-        pwm_lsb_nanoseconds: 0,
-        pwm_dither_bits: 0,
-        brightness: 0,
-        scan_mode: 0,
-        row_address_type: 0,
-        multiplexing: 0,
-        disable_hardware_pulsing: false,
-        show_refresh_rate: false,
-        inverse_colors: false,
-        led_rgb_sequence: led_rgb_sequence.as_ptr(),
-        pixel_mapper_config: pixel_mapper_config.as_ptr(),
-        panel_type: panel_type.as_ptr(),
-        limit_refresh_rate_hz: 0,
-    };
 
-    let drop_priv_user = std::ffi::CString::new("").unwrap();
-    let drop_priv_group = std::ffi::CString::new("").unwrap();
-    let mut rgb_runtime_opt =  bindings::RGBLedRuntimeOptions {
-        gpio_slowdown: 1,
-        daemon: 0,
-        drop_privileges: 0,
-        do_gpio_init: false,
-        drop_priv_user: drop_priv_user.as_ptr(),
-        drop_priv_group: drop_priv_group.as_ptr(),
+    let mut backend = create_backend(&config)?;
+    let terminal_preview = config.display.backend == BackendKind::Terminal
+        || config.display.mirror.contains(&BackendKind::Terminal);
+    // the messages would be drawn over the preview, unless stderr already goes elsewhere
+    let _log_redirect = if terminal_preview && io::stderr().is_terminal() {
+        let path = Path::new(&settings.state_dir).join("board.log");
+        eprintln!("logging to {}", path.display());
+        Some(log::redirect(&path)?)
+    } else {
+        None
     };
-    let matrix;
-    let fonttt;
-    let font_c_path; 
-    let canvas;
-    unsafe {
-      font_c_path = std::ffi::CString::new("myfont.bdf").unwrap();
-      matrix = bindings::led_matrix_create_from_options_and_rt_options(&mut rgb_option, &mut rgb_runtime_opt);
-     canvas = bindings::led_matrix_get_canvas(matrix);
-      fonttt = bindings::load_font(font_c_path.as_ptr());
-         
-      bindings::led_canvas_fill(canvas, 0, 0 ,0);
-      bindings::draw_text(canvas, fonttt, 0,  16,155,155,0, font_c_path.as_ptr(), 0 );
-
-
+    log!("canvas: {}x{}", backend.width(), backend.height());
+
+    let line_chars = line_chars(&settings.font, backend.width())?;
+    let sources = Sources::load(&config, &Sources::default())?;
+    let mut dbl: DashBoard = DashBoard::from_config(&config, line_chars, sources);
+    let mut status = DisplayStatus::new(backend.brightness());
+
+    let (status_report, status_receiver) = tokio::sync::watch::channel(StatusReport::default());
+    // the board keeps running without its remote, e.g. when the port is taken
+    let mut web_events = None;
+    if config.web.enabled {
+        match web::start_server(&config.web, status_receiver) {
+            Ok(e) => { web_events = Some(e); }
+            Err(e) => { log!("remote control disabled: {e}"); }
+        }
     }
 
-    // bindings::rgb_matrix_RGBMatrix();
+    // leave the loop on ctrl-c so the backend is dropped and can finish its work
+    let stop_requested = Arc::new(AtomicBool::new(false));
+    let stop_flag = stop_requested.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            stop_flag.store(true, Ordering::Relaxed);
+        }
+    });
+
     let mut index_f :u128 = 0;
     loop {
-        if !alive {
+        if backend.is_closed() || stop_requested.load(Ordering::Relaxed) {
             break;
         }
         // Calculate the elapsed time since the last frame
         let elapsed = last_frame_time.elapsed();
 
- //       update(&sdl_context, &mut indexx, &mut alive);
+        if let Ok(new_config) = config_updates.try_recv() {
+            // the font and the matrix itself are only read at startup,
+            // the timetable only when its path changes
+            match Sources::load(&new_config, &dbl.sources) {
+                Ok(sources) => {
+                    frame_time = new_config.settings.frame_time();
+                    wait_frames = (new_config.settings.fps * STEP * new_config.settings.second_num_wait) as i32;
+                    dbl.reload(&new_config, sources);
+                    indexx = indexx.min(wait_frames);
+                }
+                Err(e) => { log!("{e}, keeping the previous configuration"); }
+            }
+        }
+
+        let mut events = backend.poll_events();
+        if let Some(web_events) = &mut web_events {
+            while let Ok(event) = web_events.try_recv() {
+                events.push(event);
+            }
+        }
+        for event in events {
+            match event {
+                WebEvent::BrightnessUp => { status.increase_light(); }
+                WebEvent::BrightnessDown => { status.decrease_light(); }
+                WebEvent::NextDest => {
+                    dbl.show_next_entry();
+                    indexx = wait_frames;
+                }
+                WebEvent::PrevDest => {
+                    dbl.show_prev_entry();
+                    indexx = wait_frames;
+                }
+                WebEvent::Reset => {
+                    dbl.reset();
+                    indexx = wait_frames;
+                }
+                WebEvent::TogglePlay => { status.is_playing = !status.is_playing; }
+            }
+        }
+        if backend.brightness() != status.brightness_level {
+            backend.set_brightness(status.brightness_level);
+        }
+        let report = StatusReport {
+            brightness: status.brightness_level,
+            playing: status.is_playing,
+            page: dbl.curr_page,
+            entry: dbl.current_label(),
+            quota: dbl.sources.quota().map(|quota| quota.usage()),
+            entries: dbl.entry_statuses(),
+        };
+        if *status_report.borrow() != report {
+            status_report.send_replace(report);
+        }
 
-        let mini = LINE_HEIGHT * (dbl.get_curr_page_size()) as i32 * -1;
+        let mini = -(LINE_HEIGHT * (dbl.get_curr_page_size()) as i32);
         if indexx < mini + STEP as i32 {
-            indexx = (FPS * STEP * SECOND_NUM_WAIT) as i32;
+            indexx = wait_frames;
             dbl.move_next_page_element();
         }
-        indexx -= STEP as i32;
+        // paused: the current entry stays on screen, its departures keep being updated
+        if status.is_playing {
+            indexx -= STEP as i32;
+        }
 
         dbl.update_content().await;
         let lines = dbl.get_content();
 
-        // Render your game here...
-//        printooo(&mut canvas, &lines, &font, indexx);
-//
-//
-
-    unsafe {
-    
-              bindings::led_canvas_fill(canvas, 0, 0 ,0);
-    }
-
- 
-
-       let bri = get_brightness_from_time();
-//        println!("BRI={}" , bri);
-    //      println!("R={}" , r);
-    //    println!("G={}" , g);
-     //   println!("B={}" , b);
-
-
-
-
+        render_lines(backend.as_mut(), &lines, indexx);
 
-    for (i, line) in lines.iter().enumerate() {
-        // render a surface, and convert it to a texture bound to the canvas
-
-    let (r1, g1, b1) = line.color;
-      let r : u8 = (r1 as f32 * bri) as u8;
-        let g : u8 = (g1 as f32 * bri) as u8;
-        let b : u8 = (b1 as f32 * bri) as u8;
-
-
-        // If the example text is too big for the screen, downscale it (and center irregardless)
-        let padding = 16;
-        
-        let mut change_val = 0;
-        if indexx < 0 {
-            change_val = indexx;
-        }
-        let upval: i32 = 10 + 16 * i as i32 + change_val as i32;
-        unsafe {
-              bindings::draw_text(canvas, fonttt, 0,  upval, r, g, b, line.text.as_str().as_ptr(), 0 );
-        }
-
-    }
-
-
-
-        //canvas.present();
-        if elapsed.is_err() {
-            println!("Err with system time");
-            thread::sleep(Duration::from_secs(1));
-        } else {
+        if let Ok(elapsed) = elapsed {
             // Calculate the remaining time to reach the target frame time
-            let remaining_time = FRAME_TIME.checked_sub(elapsed.unwrap());
+            let remaining_time = frame_time.checked_sub(elapsed);
 
             // If there's remaining time, sleep for that duration
             if let Some(remaining) = remaining_time {
@@ -975,12 +1060,13 @@ async fn run(font_path: &Path) -> Result<(), String> {
             }
            // let aa = 1000 / remaining_time.unwrap().as_millis();
 
-            print!("\rframe: {index_f} ");
-        }
-
-        let res = io::stdout().flush();
-        if res.is_err() {
-            println!("Could not flush!!");
+            // it would be drawn over the terminal preview
+            if !terminal_preview {
+                eprint!("\rframe: {index_f} ");
+            }
+        } else {
+            log!("Err with system time");
+            thread::sleep(Duration::from_secs(1));
         }
 
         index_f += 1;
@@ -994,18 +1080,81 @@ async fn run(font_path: &Path) -> Result<(), String> {
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
-    let args: Vec<_> = env::args().collect();
+    #[cfg(feature = "sdl")]
+    log!("linked sdl2: {}", sdl2::version::version());
+
+    // --led-* flags override the [matrix] table, like the rpi-rgb-led-matrix examples
+    let mut led_flags = vec![];
+    let mut config_path = None;
+    for arg in env::args().skip(1) {
+        match arg.strip_prefix("--") {
+            Some(flag) => {
+                let (name, value) = match flag.split_once('=') {
+                    Some((name, value)) => { (name.to_string(), Some(value.to_string())) }
+                    None => { (flag.to_string(), None) }
+                };
+                led_flags.push((name, value));
+            }
+            None => { config_path = Some(arg); }
+        }
+    }
 
-    println!("linked sdl2_ttf: {}", sdl2::ttf::get_linked_version());
+    match config_path {
+        None => {
+            println!("Usage: ./demo [--led-rows=64 --led-chain=3 ...] config.toml")
+        }
+        Some(config_path) => {
+            log!("./demo {config_path}");
+            run(Path::new(&config_path), &led_flags).await?;
+        }
+    }
 
-    if args.len() < 2 {
-        println!("Usage: ./demo font.[ttf|ttc|fon]")
-    } else {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use quota::Quota;
+
+    use super::*;
 
-        println!("./demo {}", &args[1] );
-        let path: &Path = Path::new(&args[1]);
-        run(path).await?;
+    fn line() -> DashBoardBusLine {
+        let entry: EntryConfig = toml::from_str("label = \"T2\"\nfrom = \"Zurich\"\nto = \"Bern\"").unwrap();
+        DashBoardBusLine::new(&entry, &Sources::default(), Duration::from_secs(30), 32)
     }
 
-    Ok(())
+    // the plan of one entry at noon UTC with `daily_limit` requests left
+    fn plan(daily_limit: u32, noon: SystemTime) -> QuotaPlan {
+        let path = env::temp_dir().join(format!("led-display-{}-retry-{daily_limit}", std::process::id())).join("quota.json");
+        let always = ActiveHours::default();
+        Quota::load(path, daily_limit).plan([vec![(1, &always)]].into_iter(), noon)
+    }
+
+    #[test]
+    fn retries_wait_for_the_quota_share() {
+        let noon: SystemTime = Utc.from_utc_datetime(&Utc::now().date_naive().and_hms_opt(12, 0, 0).unwrap()).into();
+        let mut line = line();
+        line.last_update = noon;
+        line.last_attempt = noon;
+        line.retry_at = Some(noon + Duration::from_secs(5));
+        // 12 hours left for 24 requests
+        let plan = plan(24, noon);
+        assert!(!line.refresh_due(Some(&plan), noon + Duration::from_secs(10)));
+        assert!(!line.refresh_due(Some(&plan), noon + Duration::from_secs(1799)));
+        assert!(line.refresh_due(Some(&plan), noon + Duration::from_secs(1800)));
+        // the backoff still applies when it is the later one
+        line.retry_at = Some(noon + Duration::from_secs(2000));
+        assert!(!line.refresh_due(Some(&plan), noon + Duration::from_secs(1900)));
+        // without a quota the backoff alone decides
+        assert!(line.refresh_due(None, noon + Duration::from_secs(2000)));
+    }
+
+    #[test]
+    fn no_retry_once_the_quota_is_spent() {
+        let noon: SystemTime = Utc.from_utc_datetime(&Utc::now().date_naive().and_hms_opt(12, 0, 0).unwrap()).into();
+        let mut line = line();
+        line.retry_at = Some(noon);
+        assert!(!line.refresh_due(Some(&plan(0, noon)), noon + Duration::from_secs(24 * 3600)));
+    }
 }
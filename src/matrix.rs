@@ -0,0 +1,199 @@
+// Hardware settings of the led panels, the RGBLedMatrixOptions and
+// RGBLedRuntimeOptions of the rpi-rgb-led-matrix library. They come from the
+// [matrix] table of the config file and can be overridden with the same
+// --led-* flags as the library's own examples.
+
+use serde::{Deserialize, Serialize};
+
+const HARDWARE_MAPPINGS: [&str; 7] = [
+    "regular", "regular-pi1", "adafruit-hat", "adafruit-hat-pwm", "classic", "classic-pi1", "compute-module",
+];
+const PANEL_TYPES: [&str; 3] = ["", "FM6126A", "FM6127"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct MatrixConfig {
+    pub hardware_mapping: String,
+    pub rows: u32,
+    pub cols: u32,
+    pub chain_length: u32,
+    pub parallel: u32,
+    // the optional values use the library default when missing
+    pub pwm_bits: Option<u32>,
+    pub pwm_lsb_nanoseconds: Option<u32>,
+    pub dither: Option<u32>,
+    pub brightness: Option<u32>,
+    pub scan_mode: u32,
+    pub row_address_type: u32,
+    pub multiplexing: u32,
+    pub disable_hardware_pulsing: bool,
+    pub show_refresh_rate: bool,
+    pub inverse_colors: bool,
+    pub led_rgb_sequence: String,
+    pub pixel_mapper_config: String,
+    pub panel_type: String,
+    // 0 means no limit
+    pub limit_refresh_rate_hz: u32,
+    pub gpio_slowdown: u32,
+    pub daemon: bool,
+    pub drop_privileges: bool,
+    pub drop_priv_user: String,
+    pub drop_priv_group: String,
+}
+
+impl Default for MatrixConfig {
+    // three 64x64 panels chained, the original wall
+    fn default() -> MatrixConfig {
+        MatrixConfig {
+            hardware_mapping: "regular".to_string(),
+            rows: 64,
+            cols: 64,
+            chain_length: 3,
+            parallel: 1,
+            pwm_bits: None,
+            pwm_lsb_nanoseconds: None,
+            dither: None,
+            brightness: None,
+            scan_mode: 0,
+            row_address_type: 0,
+            multiplexing: 0,
+            disable_hardware_pulsing: false,
+            show_refresh_rate: false,
+            inverse_colors: false,
+            led_rgb_sequence: "RGB".to_string(),
+            pixel_mapper_config: String::new(),
+            panel_type: String::new(),
+            limit_refresh_rate_hz: 0,
+            gpio_slowdown: 1,
+            daemon: false,
+            drop_privileges: false,
+            drop_priv_user: String::new(),
+            drop_priv_group: String::new(),
+        }
+    }
+}
+
+fn check_range(key: &str, value: u32, min: u32, max: u32) -> Result<(), String> {
+    if value < min || value > max {
+        return Err(format!("{key}: must be between {min} and {max} (got {value})"));
+    }
+    Ok(())
+}
+
+fn parse_number(flag: &str, value: Option<&str>) -> Result<u32, String> {
+    let value = value.ok_or_else(|| format!("--{flag}: a value is expected, e.g. --{flag}=1"))?;
+    value.parse().map_err(|_| format!("--{flag}: `{value}` is not a positive number"))
+}
+
+fn parse_text(flag: &str, value: Option<&str>) -> Result<String, String> {
+    value
+        .map(|v| v.to_string())
+        .ok_or_else(|| format!("--{flag}: a value is expected, e.g. --{flag}=value"))
+}
+
+impl MatrixConfig {
+    // errors are relative to the [matrix] table
+    pub fn validate(&self) -> Result<(), String> {
+        if !HARDWARE_MAPPINGS.contains(&self.hardware_mapping.as_str()) {
+            return Err(format!("hardware_mapping: `{}` is not one of {}", self.hardware_mapping, HARDWARE_MAPPINGS.join(", ")));
+        }
+        if !self.rows.is_multiple_of(2) {
+            return Err(format!("rows: must be an even number (got {})", self.rows));
+        }
+        check_range("rows", self.rows, 4, 64)?;
+        check_range("cols", self.cols, 1, 256)?;
+        check_range("chain_length", self.chain_length, 1, 32)?;
+        check_range("parallel", self.parallel, 1, 6)?;
+        if let Some(pwm_bits) = self.pwm_bits {
+            check_range("pwm_bits", pwm_bits, 1, 11)?;
+        }
+        if let Some(nanoseconds) = self.pwm_lsb_nanoseconds {
+            check_range("pwm_lsb_nanoseconds", nanoseconds, 50, 3000)?;
+        }
+        if let Some(dither) = self.dither {
+            check_range("dither", dither, 0, 2)?;
+        }
+        if let Some(brightness) = self.brightness {
+            check_range("brightness", brightness, 1, 100)?;
+        }
+        check_range("scan_mode", self.scan_mode, 0, 1)?;
+        check_range("row_address_type", self.row_address_type, 0, 5)?;
+        // the library's pixel multiplexers, 0 for direct panels
+        check_range("multiplexing", self.multiplexing, 0, 18)?;
+        check_range("limit_refresh_rate_hz", self.limit_refresh_rate_hz, 0, 10000)?;
+        check_range("gpio_slowdown", self.gpio_slowdown, 0, 4)?;
+
+        let mut sequence: Vec<char> = self.led_rgb_sequence.to_uppercase().chars().collect();
+        sequence.sort_unstable();
+        if sequence != ['B', 'G', 'R'] {
+            return Err(format!("led_rgb_sequence: must be a permutation of RGB (got `{}`)", self.led_rgb_sequence));
+        }
+        if !PANEL_TYPES.iter().any(|t| t.eq_ignore_ascii_case(&self.panel_type)) {
+            return Err(format!("panel_type: `{}` is not one of FM6126A, FM6127 or empty", self.panel_type));
+        }
+        Ok(())
+    }
+
+    // applies one --led-* flag, `value` is what follows the `=`
+    pub fn apply_flag(&mut self, flag: &str, value: Option<&str>) -> Result<(), String> {
+        match flag {
+            "led-gpio-mapping" => { self.hardware_mapping = parse_text(flag, value)?; }
+            "led-rows" => { self.rows = parse_number(flag, value)?; }
+            "led-cols" => { self.cols = parse_number(flag, value)?; }
+            "led-chain" => { self.chain_length = parse_number(flag, value)?; }
+            "led-parallel" => { self.parallel = parse_number(flag, value)?; }
+            "led-pwm-bits" => { self.pwm_bits = Some(parse_number(flag, value)?); }
+            "led-pwm-lsb-nanoseconds" => { self.pwm_lsb_nanoseconds = Some(parse_number(flag, value)?); }
+            "led-pwm-dither-bits" => { self.dither = Some(parse_number(flag, value)?); }
+            "led-brightness" => { self.brightness = Some(parse_number(flag, value)?); }
+            "led-scan-mode" => { self.scan_mode = parse_number(flag, value)?; }
+            "led-row-addr-type" => { self.row_address_type = parse_number(flag, value)?; }
+            "led-multiplexing" => { self.multiplexing = parse_number(flag, value)?; }
+            "led-no-hardware-pulse" => { self.disable_hardware_pulsing = true; }
+            "led-show-refresh" => { self.show_refresh_rate = true; }
+            "led-inverse" => { self.inverse_colors = true; }
+            "led-rgb-sequence" => { self.led_rgb_sequence = parse_text(flag, value)?; }
+            "led-pixel-mapper" => { self.pixel_mapper_config = parse_text(flag, value)?; }
+            "led-panel-type" => { self.panel_type = parse_text(flag, value)?; }
+            "led-limit-refresh" => { self.limit_refresh_rate_hz = parse_number(flag, value)?; }
+            "led-slowdown-gpio" => { self.gpio_slowdown = parse_number(flag, value)?; }
+            "led-daemon" => { self.daemon = true; }
+            "led-drop-privs" => { self.drop_privileges = true; }
+            "led-no-drop-privs" => { self.drop_privileges = false; }
+            "led-drop-priv-user" => { self.drop_priv_user = parse_text(flag, value)?; }
+            "led-drop-priv-group" => { self.drop_priv_group = parse_text(flag, value)?; }
+            _ => { return Err(format!("--{flag}: unknown flag")); }
+        }
+        Ok(())
+    }
+
+    // size of the canvas once the panels are chained and the pixel mappers applied,
+    // the rgbmatrix backend asks the library instead
+    pub fn canvas_size(&self) -> (u32, u32) {
+        let mut width = self.cols * self.chain_length;
+        let mut height = self.rows * self.parallel;
+        for mapper in self.pixel_mapper_config.split(';').map(str::trim) {
+            let (name, parameter) = mapper.split_once(':').unwrap_or((mapper, ""));
+            match name.to_ascii_lowercase().as_str() {
+                // the chain is folded in two
+                "u-mapper" => {
+                    width /= 2;
+                    height *= 2;
+                }
+                // the panels of a chain are stacked on top of each other
+                "v-mapper" => {
+                    width = self.cols * self.parallel;
+                    height = self.rows * self.chain_length;
+                }
+                "rotate" => {
+                    let angle: u32 = parameter.trim().parse().unwrap_or(0);
+                    if angle % 180 == 90 {
+                        (width, height) = (height, width);
+                    }
+                }
+                _ => {}
+            }
+        }
+        (width, height)
+    }
+}
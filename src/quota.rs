@@ -0,0 +1,300 @@
+// Daily request budget of transport.opendata.ch (1000 requests per UTC day).
+// Every API request takes one from the budget, the count is saved in the
+// state directory so a restart does not reset it. The refresh interval of
+// each entry is stretched so the remaining requests last until midnight UTC,
+// shared according to the entry priority and active hours.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use chrono::{Duration as ChronoDuration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: u32 = 24 * 3600;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct QuotaState {
+    // UTC day, YYYY-MM-DD
+    day: String,
+    used: u32,
+}
+
+// shown by the status API
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct QuotaUsage {
+    pub used: u32,
+    pub limit: u32,
+    pub remaining: u32,
+}
+
+pub struct Quota {
+    daily_limit: AtomicU32,
+    path: PathBuf,
+    state: Mutex<QuotaState>,
+}
+
+fn utc_day(now: SystemTime) -> String {
+    let now: chrono::DateTime<Utc> = now.into();
+    now.format("%Y-%m-%d").to_string()
+}
+
+fn next_utc_midnight(now: SystemTime) -> SystemTime {
+    let now: chrono::DateTime<Utc> = now.into();
+    let tomorrow = now.date_naive() + ChronoDuration::days(1);
+    Utc.from_utc_datetime(&tomorrow.and_time(NaiveTime::MIN)).into()
+}
+
+impl Quota {
+    // the count of today is read back from `path`, a missing or unreadable file starts at 0
+    pub fn load(path: PathBuf, daily_limit: u32) -> Quota {
+        let today = utc_day(SystemTime::now());
+        let saved = fs::read_to_string(&path).ok()
+            .and_then(|text| serde_json::from_str::<QuotaState>(&text).ok())
+            .filter(|state| state.day == today);
+        let state = match saved {
+            Some(e) => {
+                log!("quota: {} requests already used today", e.used);
+                e
+            }
+            None => { QuotaState { day: today, used: 0 } }
+        };
+        Quota { daily_limit: AtomicU32::new(daily_limit), path, state: Mutex::new(state) }
+    }
+
+    pub fn set_daily_limit(&self, daily_limit: u32) {
+        self.daily_limit.store(daily_limit, Ordering::Relaxed);
+    }
+
+    // the count starts again at 0 with each UTC day
+    fn today(&self) -> MutexGuard<'_, QuotaState> {
+        let mut state = self.state.lock().unwrap();
+        let today = utc_day(SystemTime::now());
+        if state.day != today {
+            *state = QuotaState { day: today, used: 0 };
+        }
+        state
+    }
+
+    // requests of the current UTC day
+    pub fn usage(&self) -> QuotaUsage {
+        let used = self.today().used;
+        let limit = self.daily_limit.load(Ordering::Relaxed);
+        QuotaUsage { used, limit, remaining: limit.saturating_sub(used) }
+    }
+
+    // takes one request from the budget, false when the day is spent
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.today();
+        if state.used >= self.daily_limit.load(Ordering::Relaxed) {
+            return false;
+        }
+        state.used += 1;
+        if let Err(e) = self.save(&state) {
+            log!("quota: cannot save {}: {e}", self.path.display());
+        }
+        true
+    }
+
+    fn save(&self, state: &QuotaState) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let text = serde_json::to_string(state).map_err(|e| e.to_string())?;
+        fs::write(&self.path, text).map_err(|e| e.to_string())
+    }
+
+    // `groups` are the (priority, active hours) of the entries using the API, one group
+    // per request: the entries sharing it cost the requests of the most demanding one
+    pub fn plan<'a>(&self, groups: impl Iterator<Item = Vec<(u32, &'a ActiveHours)>>, now: SystemTime) -> QuotaPlan {
+        let usage = self.usage();
+        let midnight = next_utc_midnight(now);
+        let weighted_seconds = groups
+            .map(|entries| {
+                entries.iter()
+                    .map(|(priority, hours)| *priority as f64 * hours.active_seconds(now, midnight) as f64)
+                    .fold(0.0, f64::max)
+            })
+            .sum();
+        QuotaPlan { remaining: usage.remaining, weighted_seconds }
+    }
+}
+
+pub struct QuotaPlan {
+    remaining: u32,
+    // sum over the requests of priority x active seconds left today
+    weighted_seconds: f64,
+}
+
+impl QuotaPlan {
+    // time between two requests of an entry, None once the budget of the day is spent;
+    // each entry gets a share of the remaining requests proportional to priority x active time
+    pub fn interval(&self, priority: u32, refresh_interval: Duration) -> Option<Duration> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let spread = self.weighted_seconds / (self.remaining as f64 * priority.max(1) as f64);
+        Some(refresh_interval.max(Duration::from_secs_f64(spread)))
+    }
+}
+
+// local time ranges, "06:30-09:00", an end before the start runs past midnight
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActiveHours {
+    // seconds after midnight
+    ranges: Vec<(u32, u32)>,
+}
+
+fn parse_clock(text: &str) -> Option<u32> {
+    let time = NaiveTime::parse_from_str(text.trim(), "%H:%M").ok()?;
+    Some(time.signed_duration_since(NaiveTime::MIN).num_seconds() as u32)
+}
+
+impl ActiveHours {
+    // an empty list means always active
+    pub fn parse(ranges: &[String]) -> Result<ActiveHours, String> {
+        let mut parsed = vec![];
+        for (k, range) in ranges.iter().enumerate() {
+            let bounds = range.split_once('-')
+                .and_then(|(start, end)| Some((parse_clock(start)?, parse_clock(end)?)));
+            match bounds {
+                Some((start, end)) if start != end => { parsed.push((start, end)); }
+                _ => { return Err(format!("[{k}]: expected HH:MM-HH:MM, got `{range}`")); }
+            }
+        }
+        Ok(ActiveHours { ranges: parsed })
+    }
+
+    // the ranges of one local day as (start, end) instants, split at midnight
+    fn ranges_of(&self, date: NaiveDate) -> Vec<(SystemTime, SystemTime)> {
+        let at = |seconds: u32| -> Option<SystemTime> {
+            let midnight = Local.from_local_datetime(&date.and_time(NaiveTime::MIN)).earliest()?;
+            Some((midnight + ChronoDuration::seconds(seconds as i64)).into())
+        };
+        let mut found = vec![];
+        for &(start, end) in &self.ranges {
+            let pieces = if start < end { vec![(start, end)] } else { vec![(start, SECONDS_PER_DAY), (0, end)] };
+            for (start, end) in pieces {
+                if let (Some(start), Some(end)) = (at(start), at(end)) {
+                    found.push((start, end));
+                }
+            }
+        }
+        found
+    }
+
+    pub fn contains(&self, now: SystemTime) -> bool {
+        if self.ranges.is_empty() {
+            return true;
+        }
+        let local: chrono::DateTime<Local> = now.into();
+        self.ranges_of(local.date_naive()).iter().any(|&(start, end)| start <= now && now < end)
+    }
+
+    // seconds of activity between `from` and `until`, at most a day apart
+    pub fn active_seconds(&self, from: SystemTime, until: SystemTime) -> u64 {
+        let total = until.duration_since(from).unwrap_or_default().as_secs();
+        if self.ranges.is_empty() {
+            return total;
+        }
+        let local: chrono::DateTime<Local> = from.into();
+        let today = local.date_naive();
+        [today, today + ChronoDuration::days(1)].iter()
+            .flat_map(|&date| self.ranges_of(date))
+            .map(|(start, end)| {
+                let start = start.max(from);
+                let end = end.min(until);
+                end.duration_since(start).unwrap_or_default().as_secs()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(name: &str, daily_limit: u32) -> Quota {
+        let path = std::env::temp_dir().join(format!("led-display-{}-{name}", std::process::id())).join("quota.json");
+        let _ = fs::remove_file(&path);
+        Quota::load(path, daily_limit)
+    }
+
+    fn hours(ranges: &[&str]) -> ActiveHours {
+        ActiveHours::parse(&ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn local_midnight(year: i32, month: u32, day: u32) -> SystemTime {
+        let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        Local.from_local_datetime(&date.and_time(NaiveTime::MIN)).earliest().unwrap().into()
+    }
+
+    #[test]
+    fn the_count_survives_a_restart() {
+        let first = quota("restart", 2);
+        assert!(first.try_acquire());
+        assert!(first.try_acquire());
+        assert!(!first.try_acquire());
+        assert_eq!(first.usage(), QuotaUsage { used: 2, limit: 2, remaining: 0 });
+
+        let again = Quota::load(first.path.clone(), 5);
+        assert_eq!(again.usage(), QuotaUsage { used: 2, limit: 5, remaining: 3 });
+    }
+
+    #[test]
+    fn the_remaining_requests_last_until_midnight() {
+        let now = SystemTime::now();
+        let seconds_left = next_utc_midnight(now).duration_since(now).unwrap().as_secs_f64();
+        let always = ActiveHours::default();
+        let plan = quota("plan", 100).plan([vec![(1, &always)], vec![(2, &always)]].into_iter(), now);
+        let interval = |priority| plan.interval(priority, Duration::from_secs(1)).unwrap().as_secs_f64();
+        assert!((interval(1) - 3.0 * seconds_left / 100.0).abs() < 1.0);
+        assert!((interval(2) - 3.0 * seconds_left / 200.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn no_interval_once_the_day_is_spent() {
+        let always = ActiveHours::default();
+        let plan = quota("spent", 0).plan([vec![(1, &always)]].into_iter(), SystemTime::now());
+        assert_eq!(plan.interval(1, Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn the_refresh_interval_is_a_minimum() {
+        let always = ActiveHours::default();
+        let plan = quota("minimum", 1_000_000).plan([vec![(1, &always)]].into_iter(), SystemTime::now());
+        assert_eq!(plan.interval(1, Duration::from_secs(60)), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn active_hours_are_parsed() {
+        assert!(ActiveHours::parse(&[]).unwrap().contains(SystemTime::now()));
+        assert_eq!(ActiveHours::parse(&["9:00".to_string()]).unwrap_err(), "[0]: expected HH:MM-HH:MM, got `9:00`");
+        assert!(ActiveHours::parse(&["08:00-08:00".to_string()]).is_err());
+    }
+
+    #[test]
+    fn active_seconds_follow_the_ranges() {
+        let midnight = local_midnight(2024, 6, 12);
+        let day = midnight + Duration::from_secs(24 * 3600);
+        assert_eq!(hours(&["06:30-09:00"]).active_seconds(midnight, day), 9000);
+        assert_eq!(hours(&["06:30-09:00", "16:00-19:30"]).active_seconds(midnight, day), 9000 + 12600);
+        // past midnight: 00:00-02:00 and 22:00-24:00
+        assert_eq!(hours(&["22:00-02:00"]).active_seconds(midnight, day), 4 * 3600);
+
+        let morning = hours(&["06:30-09:00"]);
+        assert!(morning.contains(midnight + Duration::from_secs(7 * 3600)));
+        assert!(!morning.contains(midnight + Duration::from_secs(9 * 3600)));
+    }
+
+    #[test]
+    fn a_shared_request_is_counted_once() {
+        let now = SystemTime::now();
+        let always = ActiveHours::default();
+        let quota = quota("shared", 100);
+        let alone = quota.plan([vec![(2, &always)]].into_iter(), now);
+        let shared = quota.plan([vec![(1, &always), (2, &always)]].into_iter(), now);
+        assert_eq!(alone.weighted_seconds, shared.weighted_seconds);
+    }
+}
@@ -0,0 +1,62 @@
+// Watches the config file and sends a freshly parsed Config whenever it changes
+// on disk or when the process receives SIGHUP. The render loop picks it up
+// between two frames, so the matrix never has to be re-initialised.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+
+// editors usually write the file in several steps, polling is enough here
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+pub fn watch_config(path: PathBuf) -> Result<mpsc::UnboundedReceiver<Config>, String> {
+    let mut hangup = signal(SignalKind::hangup()).map_err(|e| format!("cannot listen to SIGHUP: {e}"))?;
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut last_modified = modified_time(&path);
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let modified = modified_time(&path);
+                    // a missing file is most likely being replaced, wait for the new one
+                    if modified.is_none() || modified == last_modified {
+                        continue;
+                    }
+                    last_modified = modified;
+                    log!("\nconfig file {} changed, reloading", path.display());
+                }
+                received = hangup.recv() => {
+                    if received.is_none() {
+                        break;
+                    }
+                    last_modified = modified_time(&path);
+                    log!("\nSIGHUP received, reloading {}", path.display());
+                }
+            }
+
+            match Config::load(&path) {
+                Ok(config) => {
+                    if sender.send(config).is_err() {
+                        // the render loop is gone
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log!("keeping the previous configuration, {e}");
+                }
+            }
+        }
+    });
+
+    Ok(receiver)
+}
@@ -0,0 +1,174 @@
+// Decides which DashBoard page is on screen. A page stays visible for a dwell
+// time or for a number of scroll cycles (one cycle = one entry scrolled away),
+// sticky pages are never left automatically.
+
+use std::time::{Duration, SystemTime};
+
+use crate::config::{Config, PageConfig};
+
+enum PageDuration {
+    Dwell(Duration),
+    Cycles(u32),
+}
+
+struct PageSlot {
+    duration: PageDuration,
+    sticky: bool,
+}
+
+impl PageSlot {
+    fn from_config(page: &PageConfig) -> PageSlot {
+        let duration = match (page.dwell, page.cycles) {
+            (Some(secs), _) => PageDuration::Dwell(Duration::from_secs(secs)),
+            (None, Some(cycles)) => PageDuration::Cycles(cycles),
+            // by default every entry of the page is shown once
+            (None, None) => PageDuration::Cycles(page.entries.len() as u32),
+        };
+        PageSlot { duration, sticky: page.sticky }
+    }
+}
+
+pub struct PageScheduler {
+    slots: Vec<PageSlot>,
+    current: usize,
+    shown_since: SystemTime,
+    cycles_done: u32,
+    // page shown after the current scroll cycle, decided when the cycle starts
+    // so the content scrolling in does not change halfway through
+    planned: Option<usize>,
+}
+
+impl PageScheduler {
+    pub fn from_config(config: &Config) -> PageScheduler {
+        PageScheduler {
+            slots: config.pages.iter().map(PageSlot::from_config).collect(),
+            current: 0,
+            shown_since: SystemTime::now(),
+            cycles_done: 0,
+            planned: None,
+        }
+    }
+
+    pub fn planned(&self) -> usize {
+        self.planned.unwrap_or(self.current)
+    }
+
+    fn should_leave(&self, now: SystemTime) -> bool {
+        let slot = &self.slots[self.current];
+        if slot.sticky || self.slots.len() < 2 {
+            return false;
+        }
+        match slot.duration {
+            PageDuration::Dwell(dwell) => {
+                now.duration_since(self.shown_since).map(|shown| shown >= dwell).unwrap_or(false)
+            }
+            PageDuration::Cycles(cycles) => self.cycles_done + 1 >= cycles,
+        }
+    }
+
+    // called every frame, only the first call of a cycle has an effect
+    pub fn plan(&mut self, now: SystemTime) {
+        if self.planned.is_some() {
+            return;
+        }
+        let next = if self.should_leave(now) {
+            (self.current + 1) % self.slots.len()
+        } else {
+            self.current
+        };
+        self.planned = Some(next);
+    }
+
+    // called when an entry has scrolled away, returns the page to show now
+    pub fn on_cycle_end(&mut self, now: SystemTime) -> usize {
+        self.plan(now);
+        let next = self.planned();
+        self.planned = None;
+        if next != self.current {
+            self.show(next, now);
+        } else {
+            self.cycles_done += 1;
+        }
+        self.current
+    }
+
+    pub fn show(&mut self, page: usize, now: SystemTime) {
+        self.current = page.min(self.slots.len().saturating_sub(1));
+        self.shown_since = now;
+        self.cycles_done = 0;
+        self.planned = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "[[pages.entries]]\nlabel = \"T2\"\nfrom = \"Zurich\"\nto = \"Bern\"\n";
+
+    // one [[pages]] table per item, with its options and `entries` entries
+    fn scheduler(pages: &[(&str, usize)]) -> PageScheduler {
+        let text: String = pages.iter()
+            .map(|(options, entries)| format!("[[pages]]\n{options}\n{}", ENTRY.repeat(*entries)))
+            .collect();
+        let mut scheduler = PageScheduler::from_config(&Config::parse(&text).unwrap());
+        scheduler.show(0, SystemTime::UNIX_EPOCH);
+        scheduler
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn each_entry_is_shown_once_by_default() {
+        let mut scheduler = scheduler(&[("", 2), ("", 1)]);
+        assert_eq!(scheduler.on_cycle_end(at(1)), 0);
+        assert_eq!(scheduler.on_cycle_end(at(2)), 1);
+        assert_eq!(scheduler.on_cycle_end(at(3)), 0);
+    }
+
+    #[test]
+    fn cycles_count_the_entries_scrolled() {
+        let mut scheduler = scheduler(&[("cycles = 3", 1), ("", 1)]);
+        assert_eq!(scheduler.on_cycle_end(at(1)), 0);
+        assert_eq!(scheduler.on_cycle_end(at(2)), 0);
+        assert_eq!(scheduler.on_cycle_end(at(3)), 1);
+    }
+
+    #[test]
+    fn dwell_is_checked_at_the_end_of_a_cycle() {
+        let mut scheduler = scheduler(&[("dwell = 10", 1), ("", 1)]);
+        assert_eq!(scheduler.on_cycle_end(at(5)), 0);
+        assert_eq!(scheduler.on_cycle_end(at(11)), 1);
+    }
+
+    #[test]
+    fn the_next_page_is_decided_when_the_cycle_starts() {
+        let mut scheduler = scheduler(&[("dwell = 10", 1), ("", 1)]);
+        scheduler.plan(at(5));
+        scheduler.plan(at(20));
+        assert_eq!(scheduler.planned(), 0);
+        assert_eq!(scheduler.on_cycle_end(at(20)), 0);
+        assert_eq!(scheduler.on_cycle_end(at(21)), 1);
+    }
+
+    #[test]
+    fn sticky_and_single_pages_are_not_left() {
+        let mut sticky = scheduler(&[("sticky = true\ncycles = 1", 1), ("", 1)]);
+        let mut single = scheduler(&[("dwell = 1", 1)]);
+        for seconds in 1..5 {
+            assert_eq!(sticky.on_cycle_end(at(seconds * 10)), 0);
+            assert_eq!(single.on_cycle_end(at(seconds * 10)), 0);
+        }
+        sticky.show(1, at(60));
+        assert_eq!(sticky.on_cycle_end(at(61)), 0);
+    }
+
+    #[test]
+    fn show_stays_on_an_existing_page() {
+        let mut scheduler = scheduler(&[("", 1), ("", 1)]);
+        scheduler.show(7, at(1));
+        assert_eq!(scheduler.planned(), 1);
+    }
+}
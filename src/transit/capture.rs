@@ -0,0 +1,121 @@
+// Record and replay of the opendata.ch answers. Recording saves each answer
+// with its URL and time in the capture directory; replaying serves these files
+// instead of asking the API, with the clock moved back to the first of them,
+// so a morning of the board can be shown again offline, always the same way.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::config::{CaptureConfig, CaptureMode};
+use crate::transit::FetchError;
+use crate::transit::store::fnv1a;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Captured {
+    url: String,
+    // unix milliseconds
+    recorded_at: u64,
+    status: u16,
+    // as received, even when it is not valid JSON
+    body: String,
+}
+
+impl Captured {
+    fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.recorded_at)
+    }
+}
+
+pub struct Recorder {
+    dir: PathBuf,
+}
+
+impl Recorder {
+    pub fn save(&self, url: &str, status: u16, body: &str) {
+        let recorded_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let captured = Captured { url: url.to_string(), recorded_at, status, body: body.to_string() };
+        let path = self.dir.join(format!("{recorded_at}-{:016x}.json", fnv1a(url)));
+        let written = fs::create_dir_all(&self.dir).map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(&captured).map_err(|e| e.to_string()))
+            .and_then(|text| fs::write(&path, text).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log!("capture: cannot save {}: {e}", path.display());
+        }
+    }
+}
+
+pub struct Replay {
+    // sorted by time
+    captures: Vec<Captured>,
+}
+
+impl Replay {
+    fn load(dir: &Path) -> Result<Replay, String> {
+        let entries = fs::read_dir(dir).map_err(|e| format!("capture.dir: cannot read {}: {e}", dir.display()))?;
+        let mut captures = vec![];
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let captured = fs::read_to_string(&path).map_err(|e| e.to_string())
+                .and_then(|text| serde_json::from_str::<Captured>(&text).map_err(|e| e.to_string()));
+            match captured {
+                Ok(e) => { captures.push(e); }
+                Err(e) => { log!("capture: skipping {}: {e}", path.display()); }
+            }
+        }
+        if captures.is_empty() {
+            return Err(format!("capture.dir: no capture found in {}", dir.display()));
+        }
+        captures.sort_by_key(|c| c.recorded_at);
+        Ok(Replay { captures })
+    }
+
+    fn start(&self) -> SystemTime {
+        self.captures[0].time()
+    }
+
+    // the latest answer to `url` recorded before `now`, or its first one
+    pub fn answer(&self, url: &str, now: SystemTime) -> Result<(u16, String), FetchError> {
+        let mut same_url = self.captures.iter().filter(|c| c.url == url).peekable();
+        let first = match same_url.peek() {
+            Some(e) => { *e }
+            None => { return Err(FetchError::Unavailable("replay: request not captured".to_string())); }
+        };
+        let captured = same_url.take_while(|c| c.time() <= now).last().unwrap_or(first);
+        Ok((captured.status, captured.body.clone()))
+    }
+}
+
+pub enum Capture {
+    Record(Recorder),
+    Replay(Replay),
+}
+
+// a new replay restarts the clock at its first capture
+pub fn start_if_changed(config: &CaptureConfig, previous: &Option<(CaptureConfig, Arc<Capture>)>) -> Result<Arc<Capture>, String> {
+    if let Some((previous_config, capture)) = previous {
+        if previous_config == config {
+            return Ok(capture.clone());
+        }
+    }
+    let dir = PathBuf::from(&config.dir);
+    let capture = match config.mode {
+        CaptureMode::Record => {
+            log!("capture: recording the answers in {}", dir.display());
+            Capture::Record(Recorder { dir })
+        }
+        CaptureMode::Replay => {
+            let replay = Replay::load(&dir)?;
+            log!("capture: replaying {} answers of {}", replay.captures.len(), dir.display());
+            clock::start_at(replay.start());
+            Capture::Replay(replay)
+        }
+    };
+    Ok(Arc::new(capture))
+}
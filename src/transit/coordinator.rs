@@ -0,0 +1,87 @@
+// One opendata.ch request for all the entries asking the same thing. The
+// entries querying the same endpoint and stations are merged when the config
+// is loaded (largest limit, every field one of them needs); the board refreshes
+// them together, and a request already running, or answered less than
+// SHARE_WINDOW ago, is not sent again: its answer goes to every entry asking for it.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::OnceCell;
+
+use crate::clock;
+use crate::transit::FetchError;
+
+// long enough for the entries refreshed in the same frame, short enough
+// for an answer never to be shown as fresher than it is
+const SHARE_WINDOW: Duration = Duration::from_secs(5);
+
+// what the entries of one group ask for together
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedQuery {
+    pub limit: u32,
+    pub fields: Vec<String>,
+}
+
+impl MergedQuery {
+    pub fn merge(&mut self, limit: u32, fields: &[String]) {
+        self.limit = self.limit.max(limit);
+        for field in fields {
+            if !self.fields.contains(field) {
+                self.fields.push(field.clone());
+            }
+        }
+    }
+}
+
+// the body of an answer and when it was received, on the board clock
+type Fetched = Result<(String, SystemTime), FetchError>;
+
+struct SharedRequest {
+    started: Instant,
+    body: OnceCell<Fetched>,
+}
+
+#[derive(Default)]
+pub struct RequestCoordinator {
+    // by group, see opendata::query_group
+    merged: HashMap<String, MergedQuery>,
+    // by URL
+    requests: Mutex<HashMap<String, Arc<SharedRequest>>>,
+}
+
+impl RequestCoordinator {
+    pub fn new(merged: HashMap<String, MergedQuery>) -> RequestCoordinator {
+        RequestCoordinator { merged, requests: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn merged(&self, group: &str) -> Option<&MergedQuery> {
+        self.merged.get(group)
+    }
+
+    // the body of `url` and when it was received, from `request` unless another
+    // entry just asked for it; the failures are not kept, the next entry sends its own request
+    pub async fn get(&self, url: &str, request: impl Future<Output = Result<String, FetchError>>) -> Fetched {
+        let shared = {
+            let mut requests = self.requests.lock().unwrap();
+            requests.retain(|_, r| r.started.elapsed() < SHARE_WINDOW || !r.body.initialized());
+            match requests.get(url) {
+                Some(e) => { e.clone() }
+                None => {
+                    let shared = Arc::new(SharedRequest { started: Instant::now(), body: OnceCell::new() });
+                    requests.insert(url.to_string(), shared.clone());
+                    shared
+                }
+            }
+        };
+        let body = shared.body.get_or_init(|| async { Ok((request.await?, clock::now())) }).await.clone();
+        if body.is_err() {
+            let mut requests = self.requests.lock().unwrap();
+            if requests.get(url).is_some_and(|r| Arc::ptr_eq(r, &shared)) {
+                requests.remove(url);
+            }
+        }
+        body
+    }
+}
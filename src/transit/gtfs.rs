@@ -0,0 +1,624 @@
+// Offline timetable read from a GTFS zip: the next scheduled departures from a
+// stop, optionally only the trips calling later at another stop. Used on its
+// own or as a fallback when the realtime API does not answer.
+//
+// Only what is needed to answer that question is kept: stops, routes, trips,
+// the stop times as three u32 per row and the service calendars. The trip ids
+// and stop sequences are kept to match the GTFS-RT predictions.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDate, TimeZone};
+use csv::StringRecord;
+use zip::ZipArchive;
+
+use crate::clock;
+use crate::config::EntryConfig;
+use crate::transit::{answer_of, Departure, DepartureStatus, FetchError, FetchFuture, TransitProvider, TripRef};
+
+const SECONDS_PER_DAY: i64 = 24 * 3600;
+
+struct Stop {
+    id: String,
+    name: String,
+    parent: Option<u32>,
+    platform: Option<String>,
+}
+
+struct Route {
+    id: String,
+    short_name: String,
+    category: String,
+}
+
+struct Trip {
+    id: String,
+    route: u32,
+    service: u32,
+    headsign: String,
+    // the rows of the trip in `stop_times` end here, the trips are stored one after the other
+    end_row: u32,
+}
+
+struct StopTime {
+    stop: u32,
+    sequence: u32,
+    // seconds after midnight of the service day, can go past 24:00:00
+    departure: u32,
+}
+
+struct Calendar {
+    // monday first
+    weekdays: [bool; 7],
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+pub struct Timetable {
+    stops: Vec<Stop>,
+    routes: Vec<Route>,
+    trips: Vec<Trip>,
+    stop_times: Vec<StopTime>,
+    // rows of `stop_times` calling at each stop, sorted by departure
+    departures_by_stop: Vec<Vec<u32>>,
+    calendars: HashMap<u32, Calendar>,
+    // calendar_dates.txt, true when the service is added that day
+    exceptions: HashMap<(u32, NaiveDate), bool>,
+}
+
+// column lookup by header name, GTFS does not fix the column order
+struct Columns {
+    positions: HashMap<String, usize>,
+}
+
+impl Columns {
+    fn new(headers: &StringRecord) -> Columns {
+        let positions = headers.iter().enumerate()
+            .map(|(i, h)| (h.trim_start_matches('\u{feff}').trim().to_string(), i))
+            .collect();
+        Columns { positions }
+    }
+
+    // empty when the column or the value is missing
+    fn get<'a>(&self, record: &'a StringRecord, name: &str) -> &'a str {
+        self.positions.get(name)
+            .and_then(|&i| record.get(i))
+            .map(str::trim)
+            .unwrap_or("")
+    }
+}
+
+fn read_table(archive: &mut ZipArchive<File>, name: &str, required: bool,
+              mut row: impl FnMut(&Columns, &StringRecord) -> Result<(), String>) -> Result<(), String> {
+    let file = match archive.by_name(name) {
+        Ok(e) => { e }
+        Err(_) if !required => { return Ok(()); }
+        Err(e) => { return Err(format!("{name}: {e}")); }
+    };
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(file);
+    let columns = Columns::new(reader.headers().map_err(|e| format!("{name}: {e}"))?);
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record).map_err(|e| format!("{name}: {e}"))? {
+        row(&columns, &record).map_err(|e| format!("{name} line {}: {e}", record.position().map(|p| p.line()).unwrap_or(0)))?;
+    }
+    Ok(())
+}
+
+// "25:10:00" -> seconds, empty for stops without a time
+fn parse_time(text: &str) -> Option<u32> {
+    let mut parts = text.split(':').map(|p| p.trim().parse::<u32>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(h * 3600 + m * 60 + s)
+}
+
+fn parse_date(text: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(text, "%Y%m%d").map_err(|e| format!("invalid date `{text}`: {e}"))
+}
+
+// categories as shown by the opendata.ch API when route_desc is empty
+fn category_of_route_type(route_type: &str) -> &'static str {
+    match route_type {
+        "0" | "900" => "T",
+        "1" | "401" => "M",
+        "2" | "100" | "106" | "109" => "R",
+        "3" | "700" | "702" => "B",
+        "4" | "1000" => "BAT",
+        "6" | "1300" => "GB",
+        "7" | "1400" => "FUN",
+        _ => "",
+    }
+}
+
+fn index_of(ids: &HashMap<String, u32>, id: &str, table: &str) -> Result<u32, String> {
+    ids.get(id).copied().ok_or_else(|| format!("unknown {table} `{id}`"))
+}
+
+impl Timetable {
+    pub fn load(path: &str) -> Result<Timetable, String> {
+        let started = Instant::now();
+        let file = File::open(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let timetable = Timetable::read(&mut archive).map_err(|e| format!("{path}: {e}"))?;
+        log!("gtfs: {} stops, {} trips, {} stop times loaded in {:.1}s",
+                 timetable.stops.len(), timetable.trips.len(), timetable.stop_times.len(),
+                 started.elapsed().as_secs_f32());
+        Ok(timetable)
+    }
+
+    fn read(archive: &mut ZipArchive<File>) -> Result<Timetable, String> {
+        let mut stop_ids = HashMap::new();
+        let mut stops = vec![];
+        let mut parent_ids = vec![];
+        read_table(archive, "stops.txt", true, |c, r| {
+            stop_ids.insert(c.get(r, "stop_id").to_string(), stops.len() as u32);
+            parent_ids.push(c.get(r, "parent_station").to_string());
+            let platform = c.get(r, "platform_code");
+            stops.push(Stop {
+                id: c.get(r, "stop_id").to_string(),
+                name: c.get(r, "stop_name").to_string(),
+                parent: None,
+                platform: (!platform.is_empty()).then(|| platform.to_string()),
+            });
+            Ok(())
+        })?;
+        for (stop, parent) in stops.iter_mut().zip(&parent_ids) {
+            stop.parent = stop_ids.get(parent).copied();
+        }
+
+        let mut route_ids = HashMap::new();
+        let mut routes = vec![];
+        read_table(archive, "routes.txt", true, |c, r| {
+            route_ids.insert(c.get(r, "route_id").to_string(), routes.len() as u32);
+            let description = c.get(r, "route_desc");
+            let category = if !description.is_empty() && description.len() <= 4 {
+                description.to_string()
+            } else {
+                category_of_route_type(c.get(r, "route_type")).to_string()
+            };
+            let short_name = match c.get(r, "route_short_name") {
+                "" => { c.get(r, "route_long_name").to_string() }
+                e => { e.to_string() }
+            };
+            routes.push(Route { id: c.get(r, "route_id").to_string(), short_name, category });
+            Ok(())
+        })?;
+
+        let mut service_ids: HashMap<String, u32> = HashMap::new();
+        let mut service_index = |id: &str| {
+            let next = service_ids.len() as u32;
+            *service_ids.entry(id.to_string()).or_insert(next)
+        };
+        let mut calendars = HashMap::new();
+        read_table(archive, "calendar.txt", false, |c, r| {
+            let mut weekdays = [false; 7];
+            let names = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+            for (day, name) in weekdays.iter_mut().zip(names) {
+                *day = c.get(r, name) == "1";
+            }
+            calendars.insert(service_index(c.get(r, "service_id")), Calendar {
+                weekdays,
+                start: parse_date(c.get(r, "start_date"))?,
+                end: parse_date(c.get(r, "end_date"))?,
+            });
+            Ok(())
+        })?;
+        let mut exceptions = HashMap::new();
+        read_table(archive, "calendar_dates.txt", false, |c, r| {
+            let date = parse_date(c.get(r, "date"))?;
+            exceptions.insert((service_index(c.get(r, "service_id")), date), c.get(r, "exception_type") == "1");
+            Ok(())
+        })?;
+
+        let mut trip_ids = HashMap::new();
+        let mut trips = vec![];
+        read_table(archive, "trips.txt", true, |c, r| {
+            trip_ids.insert(c.get(r, "trip_id").to_string(), trips.len() as u32);
+            trips.push(Trip {
+                id: c.get(r, "trip_id").to_string(),
+                route: index_of(&route_ids, c.get(r, "route_id"), "route_id")?,
+                service: service_index(c.get(r, "service_id")),
+                headsign: c.get(r, "trip_headsign").to_string(),
+                end_row: 0,
+            });
+            Ok(())
+        })?;
+
+        // (trip, stop_sequence, stop, departure), sorted to lay the trips out one after the other
+        let mut rows: Vec<(u32, u32, u32, u32)> = vec![];
+        read_table(archive, "stop_times.txt", true, |c, r| {
+            let time = parse_time(c.get(r, "departure_time")).or_else(|| parse_time(c.get(r, "arrival_time")));
+            // stops without a time are interpolated by GTFS consumers, we skip them
+            if let Some(departure) = time {
+                rows.push((
+                    index_of(&trip_ids, c.get(r, "trip_id"), "trip_id")?,
+                    c.get(r, "stop_sequence").parse().map_err(|_| "invalid stop_sequence".to_string())?,
+                    index_of(&stop_ids, c.get(r, "stop_id"), "stop_id")?,
+                    departure,
+                ));
+            }
+            Ok(())
+        })?;
+        rows.sort_unstable();
+
+        let mut stop_times = Vec::with_capacity(rows.len());
+        let mut departures_by_stop = vec![vec![]; stops.len()];
+        for (i, &(trip, sequence, stop, departure)) in rows.iter().enumerate() {
+            trips[trip as usize].end_row = i as u32 + 1;
+            departures_by_stop[stop as usize].push(i as u32);
+            stop_times.push(StopTime { stop, sequence, departure });
+        }
+        drop(rows);
+        // trips without stop times get an empty range, `trip_of_row` needs increasing ends
+        let mut end_row = 0;
+        for trip in &mut trips {
+            end_row = end_row.max(trip.end_row);
+            trip.end_row = end_row;
+        }
+        for rows in &mut departures_by_stop {
+            rows.sort_unstable_by_key(|&row| stop_times[row as usize].departure);
+            rows.shrink_to_fit();
+        }
+
+        Ok(Timetable { stops, routes, trips, stop_times, departures_by_stop, calendars, exceptions })
+    }
+
+    // stops called `name` (or with that stop_id), with the platforms of those stations
+    fn find_stops(&self, name: &str) -> Vec<u32> {
+        let name = name.trim();
+        let matches: HashSet<u32> = self.stops.iter().enumerate()
+            .filter(|(_, s)| s.id == name || s.name.eq_ignore_ascii_case(name))
+            .map(|(i, _)| i as u32)
+            .collect();
+        self.stops.iter().enumerate()
+            .filter(|(i, s)| matches.contains(&(*i as u32)) || s.parent.map(|p| matches.contains(&p)).unwrap_or(false))
+            .map(|(i, _)| i as u32)
+            .collect()
+    }
+
+    fn runs_on(&self, service: u32, date: NaiveDate) -> bool {
+        if let Some(&added) = self.exceptions.get(&(service, date)) {
+            return added;
+        }
+        match self.calendars.get(&service) {
+            Some(c) => { date >= c.start && date <= c.end && c.weekdays[date.weekday().num_days_from_monday() as usize] }
+            None => { false }
+        }
+    }
+
+    fn trip_of_row(&self, row: u32) -> usize {
+        // trips are laid out in order in `stop_times`
+        self.trips.partition_point(|t| t.end_row <= row)
+    }
+
+    // next departures from `from`, only the trips calling at `toward` afterwards when it is not empty
+    fn next_departures(&self, from: &[u32], toward: &HashSet<u32>, now: SystemTime, limit: usize) -> Vec<Departure> {
+        let now_local: chrono::DateTime<Local> = now.into();
+        let today = now_local.date_naive();
+        let mut found: Vec<(SystemTime, u32, NaiveDate)> = vec![];
+        // yesterday for the trips running past midnight, tomorrow for the late evening
+        for day_offset in [-1, 0, 1] {
+            let date = today + ChronoDuration::days(day_offset);
+            let midnight = match date.and_hms_opt(0, 0, 0).and_then(|d| Local.from_local_datetime(&d).earliest()) {
+                Some(e) => { e }
+                None => { continue; }
+            };
+            let since_midnight = (now_local - midnight).num_seconds();
+            if since_midnight > 2 * SECONDS_PER_DAY {
+                continue;
+            }
+            let threshold = since_midnight.max(0) as u32;
+            for &stop in from {
+                let rows = &self.departures_by_stop[stop as usize];
+                let start = rows.partition_point(|&row| self.stop_times[row as usize].departure < threshold);
+                let mut kept = 0;
+                for &row in &rows[start..] {
+                    if kept >= limit {
+                        break;
+                    }
+                    let trip = &self.trips[self.trip_of_row(row)];
+                    // the last stop of a trip is an arrival
+                    if row + 1 >= trip.end_row || !self.runs_on(trip.service, date) {
+                        continue;
+                    }
+                    if !toward.is_empty()
+                        && !self.stop_times[(row + 1) as usize..trip.end_row as usize].iter().any(|st| toward.contains(&st.stop)) {
+                        continue;
+                    }
+                    let departure = midnight + ChronoDuration::seconds(self.stop_times[row as usize].departure as i64);
+                    found.push((departure.into(), row, date));
+                    kept += 1;
+                }
+            }
+        }
+        found.sort_by_key(|&(time, _, _)| time);
+        found.truncate(limit);
+        found.into_iter().map(|(scheduled, row, date)| self.departure(scheduled, row, date)).collect()
+    }
+
+    // `date` is the service day of the trip, the GTFS-RT start_date
+    fn departure(&self, scheduled: SystemTime, row: u32, date: NaiveDate) -> Departure {
+        let trip = &self.trips[self.trip_of_row(row)];
+        let route = &self.routes[trip.route as usize];
+        let stop_time = &self.stop_times[row as usize];
+        let destination = if trip.headsign.is_empty() {
+            let last = &self.stop_times[trip.end_row as usize - 1];
+            self.stops[last.stop as usize].name.clone()
+        } else {
+            trip.headsign.clone()
+        };
+        Departure {
+            line: route.short_name.clone(),
+            category: route.category.clone(),
+            destination,
+            scheduled,
+            realtime: None,
+            platform: self.stops[stop_time.stop as usize].platform.clone(),
+            platform_changed: false,
+            status: DepartureStatus::Scheduled,
+            trip: Some(TripRef {
+                trip_id: trip.id.clone(),
+                route_id: route.id.clone(),
+                start_date: date.format("%Y%m%d").to_string(),
+                stop_id: self.stops[stop_time.stop as usize].id.clone(),
+                stop_sequence: stop_time.sequence,
+            }),
+            arrival: None,
+            legs: vec![],
+        }
+    }
+}
+
+pub struct GtfsProvider {
+    timetable: Option<Arc<TimetableSource>>,
+    from_name: String,
+    to_name: String,
+    limit: usize,
+}
+
+impl GtfsProvider {
+    pub fn new(entry: &EntryConfig, timetable: Option<Arc<TimetableSource>>) -> GtfsProvider {
+        GtfsProvider {
+            timetable,
+            from_name: entry.from.clone(),
+            to_name: entry.to.clone(),
+            limit: entry.limit as usize,
+        }
+    }
+}
+
+impl TransitProvider for GtfsProvider {
+    fn query_key(&self) -> String {
+        format!("gtfs from={} to={} limit={}", self.from_name, self.to_name, self.limit)
+    }
+
+    // the stops are looked up in the timetable of the moment, it can be replaced while the board runs
+    fn fetch(&self) -> FetchFuture {
+        let source = self.timetable.clone();
+        let from_name = self.from_name.clone();
+        let to_name = self.to_name.clone();
+        let limit = self.limit;
+        Box::pin(async move {
+            let timetable = match source {
+                Some(e) => { e.current()? }
+                None => {
+                    return Err(FetchError::Unavailable("gtfs: no timetable loaded".to_string()));
+                }
+            };
+            let from = timetable.find_stops(&from_name);
+            if from.is_empty() {
+                return Err(FetchError::Unavailable(format!("gtfs: no stop named `{from_name}`")));
+            }
+            // without `to` every departure is kept, a `to` that is not found must not drop the filter
+            let toward: HashSet<u32> = timetable.find_stops(&to_name).into_iter().collect();
+            if !to_name.trim().is_empty() && toward.is_empty() {
+                return Err(FetchError::Unavailable(format!("gtfs: no stop named `{to_name}`")));
+            }
+            let now = clock::now();
+            answer_of(timetable.next_departures(&from, &toward, now, limit), now)
+        })
+    }
+}
+
+// the timetable of [gtfs], read in the background: a national feed takes seconds
+// to parse, the board keeps running with the previous timetable meanwhile
+pub struct TimetableSource {
+    path: String,
+    loaded: Mutex<Option<Arc<Timetable>>>,
+    // why the file could not be read, when no previous timetable is left to show
+    error: Mutex<Option<String>>,
+}
+
+impl TimetableSource {
+    pub fn current(&self) -> Result<Arc<Timetable>, FetchError> {
+        if let Some(timetable) = self.loaded.lock().unwrap().clone() {
+            return Ok(timetable);
+        }
+        match self.error.lock().unwrap().clone() {
+            Some(e) => { Err(FetchError::Unavailable(format!("gtfs: {e}"))) }
+            None => { Err(FetchError::Unavailable("gtfs: loading the timetable".to_string())) }
+        }
+    }
+}
+
+// the GTFS files are read once, only a change of path loads them again
+pub fn load_if_changed(path: &str, loaded: &Option<Arc<TimetableSource>>) -> Result<Arc<TimetableSource>, String> {
+    if let Some(source) = loaded {
+        if source.path == path {
+            return Ok(source.clone());
+        }
+    }
+    // a wrong path is reported with the config, a broken file once it is read
+    File::open(path).map_err(|e| format!("gtfs.path: cannot open {path}: {e}"))?;
+    let previous = loaded.as_ref().and_then(|source| source.current().ok());
+    let source = Arc::new(TimetableSource {
+        path: path.to_string(),
+        loaded: Mutex::new(previous),
+        error: Mutex::new(None),
+    });
+    let loading = source.clone();
+    let path = path.to_string();
+    tokio::spawn(async move {
+        let result = tokio::task::spawn_blocking(move || Timetable::load(&path)).await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        match result {
+            Ok(timetable) => { *loading.loaded.lock().unwrap() = Some(Arc::new(timetable)); }
+            Err(e) => {
+                log!("gtfs: {e}");
+                *loading.error.lock().unwrap() = Some(e);
+            }
+        }
+    });
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    // trip 5 runs past midnight, trip 4 only on the 13th of June 2024
+    const TABLES: [(&str, &str); 6] = [
+        ("stops.txt", "stop_id,stop_name,parent_station,platform_code\n\
+                       HB,Zurich HB,,\nHB:1,Zurich HB,HB,1\nFB,Farbhof,,\nAL,Altstetten,,\n"),
+        ("routes.txt", "route_id,route_short_name,route_long_name,route_desc,route_type\n\
+                        R2,2,,,0\nR31,31,,,3\n"),
+        ("calendar.txt", "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+                          ALL,1,1,1,1,1,1,1,20240101,20301231\nNEVER,0,0,0,0,0,0,0,20240101,20301231\n"),
+        ("calendar_dates.txt", "service_id,date,exception_type\nNEVER,20240613,1\n"),
+        ("trips.txt", "route_id,service_id,trip_id,trip_headsign\n\
+                       R2,ALL,t1,Farbhof\nR2,ALL,t2,\nR31,ALL,t3,Altstetten\nR2,NEVER,t4,Farbhof\nR2,ALL,t5,Farbhof\n"),
+        ("stop_times.txt", "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                            t1,08:00:00,08:00:00,HB:1,1\nt1,08:10:00,08:10:00,FB,2\n\
+                            t2,08:05:00,08:05:00,HB,1\nt2,08:15:00,08:15:00,FB,2\n\
+                            t3,08:07:00,08:07:00,HB,1\nt3,08:20:00,08:20:00,AL,2\n\
+                            t4,08:09:00,08:09:00,HB,1\nt4,08:19:00,08:19:00,FB,2\n\
+                            t5,24:30:00,24:30:00,HB,1\nt5,24:40:00,24:40:00,FB,2\n"),
+    ];
+
+    // a file of its own for each call, the tests run in parallel
+    fn write_zip() -> String {
+        static WRITTEN: AtomicUsize = AtomicUsize::new(0);
+        let count = WRITTEN.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("led-display-{}-gtfs-{count}.zip", std::process::id()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (table, content) in TABLES {
+            zip.start_file(table, FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn timetable() -> Timetable {
+        Timetable::load(&write_zip()).unwrap()
+    }
+
+    // local time of June 2024
+    fn at(day: u32, hour: u32, minute: u32) -> SystemTime {
+        let date = NaiveDate::from_ymd_opt(2024, 6, day).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        Local.from_local_datetime(&date).earliest().unwrap().into()
+    }
+
+    fn departures(timetable: &Timetable, from: &str, to: &str, now: SystemTime, limit: usize) -> Vec<Departure> {
+        let toward = timetable.find_stops(to).into_iter().collect();
+        timetable.next_departures(&timetable.find_stops(from), &toward, now, limit)
+    }
+
+    fn trips(departures: &[Departure]) -> Vec<&str> {
+        departures.iter().map(|d| d.trip.as_ref().unwrap().trip_id.as_str()).collect()
+    }
+
+    #[test]
+    fn next_departures_in_time_order() {
+        let timetable = timetable();
+        let found = departures(&timetable, "Zurich HB", "", at(12, 7, 59), 4);
+        assert_eq!(trips(&found), ["t1", "t2", "t3", "t5"]);
+        assert_eq!(found[0].scheduled, at(12, 8, 0));
+        assert_eq!(found[3].scheduled, at(13, 0, 30));
+        assert_eq!(found[2].name(), "B31");
+    }
+
+    #[test]
+    fn only_the_trips_calling_at_the_destination() {
+        let timetable = timetable();
+        let found = departures(&timetable, "Zurich HB", "Farbhof", at(12, 8, 1), 3);
+        assert_eq!(trips(&found), ["t2", "t5", "t1"]);
+        assert_eq!(found[2].scheduled, at(13, 8, 0));
+        // Farbhof is the last stop of every trip
+        assert!(departures(&timetable, "FB", "", at(12, 7, 0), 3).is_empty());
+    }
+
+    #[test]
+    fn the_calendar_dates_add_a_service() {
+        let timetable = timetable();
+        assert!(!trips(&departures(&timetable, "Zurich HB", "", at(12, 7, 59), 4)).contains(&"t4"));
+        assert!(trips(&departures(&timetable, "Zurich HB", "", at(13, 7, 59), 4)).contains(&"t4"));
+    }
+
+    #[test]
+    fn a_trip_past_midnight_keeps_its_service_day() {
+        let timetable = timetable();
+        let found = departures(&timetable, "Zurich HB", "", at(13, 0, 20), 1);
+        assert_eq!(found[0].scheduled, at(13, 0, 30));
+        assert_eq!(found[0].trip.as_ref().unwrap().start_date, "20240612");
+    }
+
+    #[test]
+    fn departures_carry_their_trip() {
+        let timetable = timetable();
+        let found = departures(&timetable, "Zurich HB", "", at(12, 7, 59), 2);
+        assert_eq!(found[0].trip, Some(TripRef {
+            trip_id: "t1".to_string(),
+            route_id: "R2".to_string(),
+            start_date: "20240612".to_string(),
+            stop_id: "HB:1".to_string(),
+            stop_sequence: 1,
+        }));
+        // no headsign, the last stop of the trip
+        assert_eq!(found[1].destination, "Farbhof");
+        assert_eq!(found[1].name(), "T2");
+    }
+
+    #[tokio::test]
+    async fn unknown_stops_are_reported() {
+        let source = Arc::new(TimetableSource {
+            path: String::new(),
+            loaded: Mutex::new(Some(Arc::new(timetable()))),
+            error: Mutex::new(None),
+        });
+        let provider = |from: &str, to: &str| {
+            let entry: EntryConfig = toml::from_str(&format!("label = \"x\"\nfrom = \"{from}\"\nto = \"{to}\"")).unwrap();
+            GtfsProvider::new(&entry, Some(source.clone()))
+        };
+        let missing = |name: &str| FetchError::Unavailable(format!("gtfs: no stop named `{name}`"));
+        assert_eq!(provider("Nowhere", "").fetch().await.unwrap_err(), missing("Nowhere"));
+        assert_eq!(provider("Zurich HB", "Nowhere").fetch().await.unwrap_err(), missing("Nowhere"));
+    }
+
+    #[tokio::test]
+    async fn the_timetable_is_read_in_the_background() {
+        let source = load_if_changed(&write_zip(), &None).unwrap();
+        for _ in 0..100 {
+            if source.current().is_ok() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(source.current().unwrap().trips.len(), 5);
+        assert!(load_if_changed("/nonexistent/gtfs.zip", &Some(source)).is_err());
+    }
+
+    #[test]
+    fn the_platform_of_the_stop() {
+        let timetable = timetable();
+        let found = departures(&timetable, "Zurich HB", "", at(12, 7, 59), 2);
+        assert_eq!(found[0].platform.as_deref(), Some("1"));
+        assert_eq!(found[1].platform, None);
+    }
+}
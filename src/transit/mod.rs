@@ -0,0 +1,423 @@
+// Sources of departures. A TransitProvider owns the query of one dashboard
+// entry and returns normalized departures, whatever the API behind it.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::config::{CaptureConfig, Config, EntryConfig, ProviderKind};
+use crate::quota::Quota;
+
+mod capture;
+mod coordinator;
+mod gtfs;
+mod opendata;
+mod realtime;
+mod store;
+
+pub use capture::Capture;
+pub use store::AnswerStore;
+use coordinator::RequestCoordinator;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DepartureStatus {
+    // no realtime information
+    Scheduled,
+    OnTime,
+    Delayed,
+    // the trip does not run or skips this stop
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Departure {
+    // e.g. "4" for tram 4, "IC 1" for a train
+    pub line: String,
+    // e.g. "T", "B", "IC"
+    pub category: String,
+    // headsign of the vehicle
+    pub destination: String,
+    pub scheduled: SystemTime,
+    // predicted departure, when the source has realtime data
+    pub realtime: Option<SystemTime>,
+    // the announced platform when the source has one, otherwise the planned one
+    pub platform: Option<String>,
+    // the announced platform differs from the planned one
+    #[serde(default)]
+    pub platform_changed: bool,
+    pub status: DepartureStatus,
+    // set by the timetable sources, the realtime feed refers to the trips by id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trip: Option<TripRef>,
+    // at the destination of the connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<SystemTime>,
+    // the vehicles of a connection, empty for a plain departure
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub legs: Vec<Leg>,
+}
+
+// one vehicle of a connection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Leg {
+    pub line: String,
+    pub category: String,
+    pub from: String,
+    pub to: String,
+    pub departure: Option<SystemTime>,
+    pub arrival: Option<SystemTime>,
+    pub departure_platform: Option<String>,
+    pub arrival_platform: Option<String>,
+    // time to change from the previous leg, walk included
+    pub transfer: Option<Duration>,
+}
+
+impl Leg {
+    pub fn name(&self) -> String {
+        format!("{}{}", self.category, self.line)
+    }
+}
+
+// GTFS identifiers of the trip at the departure stop
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TripRef {
+    pub trip_id: String,
+    pub route_id: String,
+    // service day, YYYYMMDD
+    pub start_date: String,
+    pub stop_id: String,
+    pub stop_sequence: u32,
+}
+
+impl Departure {
+    // name shown on the board, "T4"
+    pub fn name(&self) -> String {
+        format!("{}{}", self.category, self.line)
+    }
+
+    // best known departure time
+    pub fn time(&self) -> SystemTime {
+        self.realtime.unwrap_or(self.scheduled)
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.time().duration_since(self.scheduled).unwrap_or_default()
+    }
+
+    // "T4>S12" when the connection needs a change
+    pub fn journey_name(&self) -> String {
+        if self.legs.len() < 2 {
+            return self.name();
+        }
+        self.legs.iter().map(|l| l.name()).collect::<Vec<_>>().join(">")
+    }
+
+    pub fn travel_time(&self) -> Option<Duration> {
+        self.arrival?.duration_since(self.time()).ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Answer {
+    pub last_update: SystemTime,
+    // sorted by departure time
+    pub departures: Vec<Departure>,
+}
+
+// why a provider has no departures to show
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError {
+    // no answer at all: DNS, connection, timeout
+    Network(String),
+    // the server answered with an error status
+    Http(u16),
+    // HTTP 429, or our daily quota is spent
+    RateLimited,
+    // the body is not the expected JSON
+    Json(String),
+    // a valid answer without any departure
+    Empty,
+    // the source cannot answer, e.g. no timetable loaded
+    Unavailable(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Network(e) => { write!(f, "network error: {e}") }
+            FetchError::Http(status) => { write!(f, "HTTP error {status}") }
+            FetchError::RateLimited => { write!(f, "rate limited") }
+            FetchError::Json(e) => { write!(f, "invalid answer: {e}") }
+            FetchError::Empty => { write!(f, "no departures") }
+            FetchError::Unavailable(e) => { write!(f, "{e}") }
+        }
+    }
+}
+
+// a random factor between 0.5 and 1, so the entries failing together do not retry together
+fn jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    delay.mul_f64(0.5 + (random % 1000) as f64 / 2000.0)
+}
+
+impl FetchError {
+    // wait before the attempt after the `failures`-th failure in a row,
+    // None when retrying early would not help and the normal refresh applies;
+    // an unavailable source costs nothing to ask again, e.g. while the timetable loads
+    pub fn backoff(&self, failures: u32, refresh_interval: Duration) -> Option<Duration> {
+        let first = match self {
+            FetchError::Empty => { return None; }
+            FetchError::Http(status) if *status < 500 => { return None; }
+            FetchError::RateLimited => { Duration::from_secs(60) }
+            FetchError::Network(_) | FetchError::Http(_) | FetchError::Json(_) | FetchError::Unavailable(_) => {
+                Duration::from_secs(5)
+            }
+        };
+        let exponential = first.saturating_mul(1 << failures.saturating_sub(1).min(16));
+        Some(jitter(exponential.min(refresh_interval)))
+    }
+}
+
+// an answer without departures is reported as FetchError::Empty,
+// `last_update` is when the source answered
+pub fn answer_of(departures: Vec<Departure>, last_update: SystemTime) -> Result<Answer, FetchError> {
+    if departures.is_empty() {
+        return Err(FetchError::Empty);
+    }
+    Ok(Answer { departures, last_update })
+}
+
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<Answer, FetchError>> + Send>>;
+
+pub trait TransitProvider: Send + Sync {
+    // identifies the query, two providers with the same key return the same departures
+    fn query_key(&self) -> String;
+
+    // the future owns what it needs, it is spawned on the runtime
+    fn fetch(&self) -> FetchFuture;
+
+    // the API request behind the departures, the entries with the same one are refreshed
+    // together and cost a single request; None for the sources without quota
+    fn shared_request(&self) -> Option<String> {
+        None
+    }
+}
+
+fn is_replay(capture: &Option<(CaptureConfig, Arc<Capture>)>) -> bool {
+    matches!(capture, Some((_, c)) if matches!(**c, Capture::Replay(_)))
+}
+
+// data shared by the providers, loaded once
+#[derive(Clone, Default)]
+pub struct Sources {
+    // the [gtfs] timetable, loaded in the background
+    gtfs: Option<Arc<gtfs::TimetableSource>>,
+    // GTFS-RT predictions of [realtime], refreshed in the background
+    realtime: Option<Arc<realtime::RealtimeFeed>>,
+    // daily budget of the opendata.ch requests, kept across config reloads
+    quota: Option<Arc<Quota>>,
+    // last answer of each entry, read back at startup
+    answers: Option<Arc<AnswerStore>>,
+    // record or replay of the API answers, with the config it was started from
+    capture: Option<(CaptureConfig, Arc<Capture>)>,
+    // transport.opendata.ch or a stand-in, ends with a slash
+    api_url: String,
+    // one request for the entries asking the same thing
+    requests: Arc<RequestCoordinator>,
+}
+
+impl Sources {
+    // what `previous` already loaded is kept when the config did not change it
+    pub fn load(config: &Config, previous: &Sources) -> Result<Sources, String> {
+        let gtfs = match &config.gtfs {
+            Some(g) => { Some(gtfs::load_if_changed(&g.path, &previous.gtfs)?) }
+            None => { None }
+        };
+        let realtime = config.realtime.as_ref()
+            .map(|r| realtime::start_if_changed(r, &previous.realtime));
+        let quota = match &previous.quota {
+            Some(e) => {
+                e.set_daily_limit(config.quota.daily_limit);
+                e.clone()
+            }
+            None => {
+                let path = Path::new(&config.settings.state_dir).join("quota.json");
+                Arc::new(Quota::load(path, config.quota.daily_limit))
+            }
+        };
+        let capture = match &config.capture {
+            Some(c) => { Some((c.clone(), capture::start_if_changed(c, &previous.capture)?)) }
+            None => { None }
+        };
+        // the board goes back to the real time when a replay ends
+        if is_replay(&previous.capture) && !is_replay(&capture) {
+            clock::reset();
+        }
+        // a replay must not replace the answers saved by the real board
+        let answers = if is_replay(&capture) {
+            None
+        } else {
+            Some(Arc::new(AnswerStore::new(Path::new(&config.settings.state_dir).join("answers"))))
+        };
+        let mut api_url = config.settings.api_url.clone();
+        if !api_url.ends_with('/') {
+            api_url.push('/');
+        }
+        let entries = config.pages.iter().flat_map(|page| &page.entries);
+        let requests = RequestCoordinator::new(opendata::merged_queries(entries));
+        Ok(Sources { gtfs, realtime, quota: Some(quota), answers, capture, api_url, requests: Arc::new(requests) })
+    }
+
+    pub fn quota(&self) -> Option<&Quota> {
+        self.quota.as_deref()
+    }
+
+    pub fn answers(&self) -> Option<&AnswerStore> {
+        self.answers.as_deref()
+    }
+
+    // overlays the latest realtime predictions, the departures without a trip are left alone
+    pub fn apply_realtime(&self, departures: &mut [Departure]) {
+        if let Some(feed) = &self.realtime {
+            feed.apply(departures);
+            // a delay can move a departure behind the next one
+            departures.sort_by_key(|departure| departure.time());
+        }
+    }
+
+    fn capture(&self) -> Option<Arc<Capture>> {
+        self.capture.as_ref().map(|(_, capture)| capture.clone())
+    }
+
+    fn http(&self) -> opendata::Http {
+        opendata::Http {
+            base_url: self.api_url.clone(),
+            quota: self.quota.clone(),
+            capture: self.capture(),
+            requests: self.requests.clone(),
+        }
+    }
+
+    fn timetable(&self) -> Option<Arc<gtfs::TimetableSource>> {
+        self.gtfs.clone()
+    }
+}
+
+// asks `fallback` when `primary` has nothing to show
+struct FallbackProvider {
+    primary: Arc<dyn TransitProvider>,
+    fallback: Arc<dyn TransitProvider>,
+}
+
+impl TransitProvider for FallbackProvider {
+    fn query_key(&self) -> String {
+        format!("{} or {}", self.primary.query_key(), self.fallback.query_key())
+    }
+
+    // the fallback is only asked when the primary source fails, an API fallback
+    // behind gtfs still spends requests and shares them with its group
+    fn shared_request(&self) -> Option<String> {
+        self.primary.shared_request().or_else(|| self.fallback.shared_request())
+    }
+
+    fn fetch(&self) -> FetchFuture {
+        let primary = self.primary.fetch();
+        let source = self.primary.query_key();
+        let fallback = self.fallback.clone();
+        Box::pin(async move {
+            match primary.await {
+                Ok(answer) => { Ok(answer) }
+                Err(e) => {
+                    log!("{e} from {source}, using the fallback");
+                    // the error of the primary source says more about why data is missing
+                    fallback.fetch().await.map_err(|_| e)
+                }
+            }
+        })
+    }
+}
+
+fn create_single_provider(kind: ProviderKind, entry: &EntryConfig, sources: &Sources) -> Arc<dyn TransitProvider> {
+    match kind {
+        ProviderKind::Connections => Arc::new(opendata::ConnectionsProvider::new(entry, sources.http())),
+        ProviderKind::Stationboard => Arc::new(opendata::StationboardProvider::new(entry, sources.http())),
+        ProviderKind::Gtfs => Arc::new(gtfs::GtfsProvider::new(entry, sources.timetable())),
+    }
+}
+
+pub fn create_provider(entry: &EntryConfig, sources: &Sources) -> Arc<dyn TransitProvider> {
+    let primary = create_single_provider(entry.provider, entry, sources);
+    match entry.fallback {
+        Some(kind) => {
+            Arc::new(FallbackProvider { primary, fallback: create_single_provider(kind, entry, sources) })
+        }
+        None => { primary }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFRESH: Duration = Duration::from_secs(600);
+
+    fn between(delay: Option<Duration>, min: u64, max: u64) -> bool {
+        delay.is_some_and(|d| d >= Duration::from_secs(min) && d <= Duration::from_secs(max))
+    }
+
+    #[test]
+    fn backoff_retries_an_unavailable_source() {
+        let loading = FetchError::Unavailable("gtfs: loading the timetable".to_string());
+        assert!(between(loading.backoff(1, REFRESH), 2, 5));
+    }
+
+    #[test]
+    fn backoff_grows_until_the_refresh_interval() {
+        let error = FetchError::Network("timeout".to_string());
+        assert!(between(error.backoff(1, REFRESH), 2, 5));
+        assert!(between(error.backoff(3, REFRESH), 10, 20));
+        assert!(between(error.backoff(30, REFRESH), 300, 600));
+        assert!(between(FetchError::Http(503).backoff(2, REFRESH), 5, 10));
+        assert!(between(FetchError::Json("eof".to_string()).backoff(1, REFRESH), 2, 5));
+    }
+
+    #[test]
+    fn backoff_waits_longer_when_rate_limited() {
+        assert!(between(FetchError::RateLimited.backoff(1, REFRESH), 30, 60));
+        assert!(between(FetchError::RateLimited.backoff(1, Duration::from_secs(20)), 10, 20));
+    }
+
+    #[test]
+    fn no_backoff_when_retrying_does_not_help() {
+        assert_eq!(FetchError::Empty.backoff(1, REFRESH), None);
+        assert_eq!(FetchError::Http(404).backoff(3, REFRESH), None);
+    }
+
+    #[test]
+    fn an_answer_needs_departures() {
+        assert_eq!(answer_of(vec![], SystemTime::now()).unwrap_err(), FetchError::Empty);
+    }
+
+    fn provider(entry: &str) -> Arc<dyn TransitProvider> {
+        create_provider(&toml::from_str(entry).unwrap(), &Sources::default())
+    }
+
+    #[test]
+    fn an_api_fallback_shares_its_request() {
+        let api = provider("label = \"a\"\nfrom = \"Zurich\"\nto = \"Bern\"");
+        let gtfs = provider("label = \"b\"\nprovider = \"gtfs\"\nfallback = \"connections\"\nfrom = \"Zurich\"\nto = \"Bern\"");
+        assert!(api.shared_request().is_some());
+        assert_eq!(gtfs.shared_request(), api.shared_request());
+        // the primary request is the one sent first
+        let board = provider("label = \"c\"\nprovider = \"stationboard\"\nfallback = \"connections\"\nfrom = \"Zurich\"\nto = \"Bern\"");
+        assert!(board.shared_request().is_some_and(|r| r.contains("stationboard")));
+    }
+}
@@ -0,0 +1,745 @@
+// transport.opendata.ch: the next connections between two stations, or the
+// stationboard of a stop.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{EntryConfig, ProviderKind};
+use crate::quota::Quota;
+use crate::clock;
+use crate::transit::coordinator::{MergedQuery, RequestCoordinator};
+use crate::transit::{answer_of, Answer, Capture, Departure, DepartureStatus, FetchError, FetchFuture, Leg, TransitProvider};
+
+// the stand-in server, run in the tests against the providers
+#[cfg(test)]
+#[path = "../bin/mock_opendata.rs"]
+#[allow(dead_code)]
+mod mock_opendata;
+
+// a server that accepts the connection and never answers must not block the entry
+#[cfg(not(test))]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// the tests wait for the mock in timeout mode
+#[cfg(test)]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+const CONNECTIONS_FIELDS: [&str; 13] = [
+    "connections/from/departureTimestamp",
+    "connections/from/delay",
+    "connections/from/platform",
+    "connections/from/prognosis/platform",
+    "connections/to/arrivalTimestamp",
+    "connections/sections/journey/category",
+    "connections/sections/journey/number",
+    "connections/sections/departure/station/name",
+    "connections/sections/departure/departureTimestamp",
+    "connections/sections/departure/platform",
+    "connections/sections/arrival/station/name",
+    "connections/sections/arrival/arrivalTimestamp",
+    "connections/sections/arrival/platform",
+];
+const STATIONBOARD_FIELDS: [&str; 7] = [
+    "stationboard/stop/departureTimestamp",
+    "stationboard/stop/delay",
+    "stationboard/stop/platform",
+    "stationboard/stop/prognosis/platform",
+    "stationboard/category",
+    "stationboard/number",
+    "stationboard/to",
+];
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Connection {
+    from: Checkpoint,
+    to: Option<Checkpoint>,
+    sections: Vec<Section>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Checkpoint {
+    #[serde(rename = "departureTimestamp")]
+    departure_timestamp: Option<u64>,
+    #[serde(rename = "arrivalTimestamp")]
+    arrival_timestamp: Option<u64>,
+    station: Option<Station>,
+    // minutes
+    delay: Option<u32>,
+    // planned platform
+    platform: Option<String>,
+    prognosis: Option<Prognosis>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Prognosis {
+    // announced platform, e.g. "7" when the train was planned on "8"
+    platform: Option<String>,
+}
+
+impl Checkpoint {
+    // the announced platform and whether it changed
+    fn platform(&self) -> (Option<String>, bool) {
+        let announced = self.prognosis.as_ref().and_then(|p| p.platform.clone());
+        match announced {
+            Some(announced) => {
+                let changed = self.platform.as_ref().is_some_and(|planned| *planned != announced);
+                (Some(announced), changed)
+            }
+            None => { (self.platform.clone(), false) }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Station {
+    name: Option<String>,
+}
+
+// a ride with `journey`, or a walk between two stations
+#[derive(Serialize, Deserialize, Debug)]
+struct Section {
+    journey: Option<Journey>,
+    departure: Option<Checkpoint>,
+    arrival: Option<Checkpoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Journey {
+    category: String,
+    number: String,
+    // terminal station, only there when requested in `fields`
+    to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Connections {
+    connections: Vec<Connection>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StationboardJourney {
+    stop: Checkpoint,
+    category: Option<String>,
+    number: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Stationboard {
+    stationboard: Vec<StationboardJourney>,
+}
+
+fn fields_or(fields: &Option<Vec<String>>, default: &[&str]) -> Vec<String> {
+    match fields {
+        Some(e) => { e.clone() }
+        None => { default.iter().map(|f| f.to_string()).collect() }
+    }
+}
+
+// `endpoint` of the API asked with `pairs`, percent-encoded: station names have
+// spaces, commas and sometimes & or +
+fn api_url(base_url: &str, endpoint: &str, pairs: &[(String, String)]) -> String {
+    let mut url = match reqwest::Url::parse(base_url).and_then(|base| base.join(endpoint)) {
+        Ok(e) => { e }
+        // checked when the config is validated
+        Err(_) => { return format!("{base_url}{endpoint}"); }
+    };
+    url.query_pairs_mut().extend_pairs(pairs);
+    url.to_string()
+}
+
+// the pairs of `params` followed by the limit and fields of the merged query
+fn query_pairs(params: &[(String, String)], query: &MergedQuery) -> Vec<(String, String)> {
+    let mut pairs = params.to_vec();
+    pairs.push(("limit".to_string(), query.limit.to_string()));
+    for field in &query.fields {
+        pairs.push(("fields[]".to_string(), field.clone()));
+    }
+    pairs
+}
+
+fn timestamp(seconds: Option<u64>) -> Option<SystemTime> {
+    seconds.map(|s| UNIX_EPOCH + Duration::from_secs(s))
+}
+
+fn station_name(checkpoint: &Option<Checkpoint>) -> String {
+    checkpoint.as_ref()
+        .and_then(|c| c.station.as_ref())
+        .and_then(|s| s.name.clone())
+        .unwrap_or_default()
+}
+
+// the rides of a connection, the walks only show up as transfer time
+fn connection_legs(connection: &Connection) -> Vec<Leg> {
+    let mut legs: Vec<Leg> = vec![];
+    for section in &connection.sections {
+        let journey = match &section.journey {
+            Some(e) => { e }
+            None => { continue; }
+        };
+        let departure = timestamp(section.departure.as_ref().and_then(|c| c.departure_timestamp));
+        let previous_arrival = legs.last().and_then(|l| l.arrival);
+        legs.push(Leg {
+            line: journey.number.clone(),
+            category: journey.category.clone(),
+            from: station_name(&section.departure),
+            to: station_name(&section.arrival),
+            departure,
+            arrival: timestamp(section.arrival.as_ref().and_then(|c| c.arrival_timestamp)),
+            departure_platform: section.departure.as_ref().and_then(|c| c.platform.clone()),
+            arrival_platform: section.arrival.as_ref().and_then(|c| c.platform.clone()),
+            transfer: match (previous_arrival, departure) {
+                (Some(arrival), Some(departure)) => { departure.duration_since(arrival).ok() }
+                _ => { None }
+            },
+        });
+    }
+    legs
+}
+
+fn realtime_of(scheduled: SystemTime, delay: Option<u32>) -> (Option<SystemTime>, DepartureStatus) {
+    match delay {
+        Some(0) => { (Some(scheduled), DepartureStatus::OnTime) }
+        Some(delay) => { (Some(scheduled + Duration::from_secs(delay as u64 * 60)), DepartureStatus::Delayed) }
+        None => { (None, DepartureStatus::Scheduled) }
+    }
+}
+
+// short enough for the panel, the full error is printed
+fn network_error(e: reqwest::Error) -> FetchError {
+    log!("request failed: {e}");
+    let reason = if e.is_timeout() {
+        "timeout".to_string()
+    } else if e.is_connect() {
+        "cannot connect".to_string()
+    } else {
+        e.without_url().to_string()
+    };
+    FetchError::Network(reason)
+}
+
+// what the requests of the providers go through
+#[derive(Clone)]
+pub struct Http {
+    // "http://transport.opendata.ch/v1/", ends with a slash
+    pub base_url: String,
+    pub quota: Option<Arc<Quota>>,
+    pub capture: Option<Arc<Capture>>,
+    pub requests: Arc<RequestCoordinator>,
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default()
+    })
+}
+
+// the body of a successful answer
+fn body_of(status: u16, text: String) -> Result<String, FetchError> {
+    if status == StatusCode::TOO_MANY_REQUESTS.as_u16() {
+        return Err(FetchError::RateLimited);
+    }
+    if !(200..300).contains(&status) {
+        return Err(FetchError::Http(status));
+    }
+    Ok(text)
+}
+
+// every request counts against the daily quota, none is sent once it is spent
+async fn request(url: String, http: Http) -> Result<String, FetchError> {
+    if let Some(quota) = &http.quota {
+        if !quota.try_acquire() {
+            log!("quota: no request left today, skipping {url}");
+            return Err(FetchError::RateLimited);
+        }
+    }
+    let response = client().get(&url).send().await.map_err(network_error)?;
+    let status = response.status().as_u16();
+    let text = response.text().await.map_err(network_error)?;
+    if let Some(Capture::Record(recorder)) = http.capture.as_deref() {
+        recorder.save(&url, status, &text);
+    }
+    body_of(status, text)
+}
+
+// the parsed answer and when it was received; a replay answers from the captures,
+// otherwise the entries making the same request share it
+async fn get_json<T: DeserializeOwned>(url: &str, http: &Http) -> Result<(T, SystemTime), FetchError> {
+    let (text_to_parse, received) = match http.capture.as_deref() {
+        Some(Capture::Replay(replay)) => {
+            let (status, text) = replay.answer(url, clock::now())?;
+            (body_of(status, text)?, clock::now())
+        }
+        _ => { http.requests.get(url, request(url.to_string(), http.clone())).await? }
+    };
+    let parsed = serde_json::from_str(text_to_parse.as_str()).map_err(|e| FetchError::Json(e.to_string()))?;
+    Ok((parsed, received))
+}
+
+fn pair(key: &str, value: &str) -> (String, String) {
+    (key.to_string(), value.to_string())
+}
+
+fn add_transportations(params: &mut Vec<(String, String)>, transportations: &[String]) {
+    for transportation in transportations {
+        params.push(pair("transportations[]", transportation));
+    }
+}
+
+// from=Zurich,HB&to=Geneve&via[]=Bern&direct=1, the query without limit and fields
+fn connections_params(entry: &EntryConfig) -> Vec<(String, String)> {
+    let mut params = vec![pair("from", &entry.from), pair("to", &entry.to)];
+    for via in &entry.via {
+        params.push(pair("via[]", via));
+    }
+    add_transportations(&mut params, &entry.transportations);
+    if entry.direct {
+        params.push(pair("direct", "1"));
+    }
+    if let Some(date) = &entry.date {
+        params.push(pair("date", date));
+    }
+    if let Some(time) = &entry.time {
+        params.push(pair("time", time));
+    }
+    if entry.is_arrival_time {
+        params.push(pair("isArrivalTime", "1"));
+    }
+    params
+}
+
+fn stationboard_params(entry: &EntryConfig) -> Vec<(String, String)> {
+    let mut params = vec![pair("station", &entry.from)];
+    add_transportations(&mut params, &entry.transportations);
+    params
+}
+
+// entries in the same group can share one request
+fn query_group(kind: ProviderKind, entry: &EntryConfig) -> Option<String> {
+    match kind {
+        ProviderKind::Connections => { Some(format!("connections {:?}", connections_params(entry))) }
+        ProviderKind::Stationboard => { Some(format!("stationboard {:?}", stationboard_params(entry))) }
+        ProviderKind::Gtfs => { None }
+    }
+}
+
+fn own_query(kind: ProviderKind, entry: &EntryConfig) -> MergedQuery {
+    let default_fields: &[&str] = match kind {
+        ProviderKind::Stationboard => { &STATIONBOARD_FIELDS }
+        _ => { &CONNECTIONS_FIELDS }
+    };
+    MergedQuery { limit: entry.limit, fields: fields_or(&entry.fields, default_fields) }
+}
+
+// the queries of the entries in each group, merged into one
+pub fn merged_queries<'a>(entries: impl Iterator<Item = &'a EntryConfig>) -> HashMap<String, MergedQuery> {
+    let mut merged: HashMap<String, MergedQuery> = HashMap::new();
+    for entry in entries {
+        for kind in [Some(entry.provider), entry.fallback].into_iter().flatten() {
+            let group = match query_group(kind, entry) {
+                Some(e) => { e }
+                None => { continue; }
+            };
+            let own = own_query(kind, entry);
+            match merged.get_mut(&group) {
+                Some(query) => { query.merge(own.limit, &own.fields); }
+                None => { merged.insert(group, own); }
+            }
+        }
+    }
+    merged
+}
+
+// what the entry asks the API, with the other entries of its group
+fn shared_query(kind: ProviderKind, entry: &EntryConfig, http: &Http) -> MergedQuery {
+    let merged = query_group(kind, entry).and_then(|group| http.requests.merged(&group).cloned());
+    merged.unwrap_or_else(|| own_query(kind, entry))
+}
+
+// from=Lausanne&to=Gen%C3%A8ve&fields[]=connections/from/departure&fields[]=connections/from/delay&limit=5
+pub struct ConnectionsProvider {
+    // stations and options, see connections_params
+    params: Vec<(String, String)>,
+    to: String,
+    // of the request, shared with the entries asking for the same connections
+    query: MergedQuery,
+    // what the entry asks for itself, the key must not change with the other entries
+    own_query: MergedQuery,
+    // connections kept for the entry
+    limit: u32,
+    http: Http,
+}
+
+impl ConnectionsProvider {
+    pub fn new(entry: &EntryConfig, http: Http) -> ConnectionsProvider {
+        ConnectionsProvider {
+            params: connections_params(entry),
+            to: entry.to.clone(),
+            query: shared_query(ProviderKind::Connections, entry, &http),
+            own_query: own_query(ProviderKind::Connections, entry),
+            limit: entry.limit,
+            http,
+        }
+    }
+
+    fn url(&self) -> String {
+        api_url(&self.http.base_url, "connections", &query_pairs(&self.params, &self.query))
+    }
+}
+
+// connections without any departure time are dropped
+fn connection_departure(connection: &Connection, destination: &str) -> Option<Departure> {
+    let scheduled = UNIX_EPOCH + Duration::from_secs(connection.from.departure_timestamp?);
+    // the first section with a journey is the vehicle we board, the others are walks
+    let journey = connection.sections.iter().find_map(|s| s.journey.as_ref());
+    let (category, line, headsign) = match journey {
+        Some(j) => { (j.category.clone(), j.number.clone(), j.to.clone()) }
+        None => { (String::new(), "err".to_string(), None) }
+    };
+    let (realtime, status) = realtime_of(scheduled, connection.from.delay);
+    let (platform, platform_changed) = connection.from.platform();
+    let legs = connection_legs(connection);
+    let arrival = timestamp(connection.to.as_ref().and_then(|c| c.arrival_timestamp))
+        .or_else(|| legs.last().and_then(|l| l.arrival));
+    Some(Departure {
+        line,
+        category,
+        destination: headsign.unwrap_or_else(|| destination.to_string()),
+        scheduled,
+        realtime,
+        platform,
+        platform_changed,
+        status,
+        trip: None,
+        arrival,
+        legs,
+    })
+}
+
+async fn fetch_connections(url: String, destination: String, limit: u32, http: Http) -> Result<Answer, FetchError> {
+    let (conn, received): (Connections, _) = get_json(&url, &http).await?;
+    let departures = conn.connections.iter()
+        .filter_map(|c| connection_departure(c, &destination))
+        .take(limit as usize)
+        .collect();
+    answer_of(departures, received)
+}
+
+impl TransitProvider for ConnectionsProvider {
+    // the entry's own query, not the merged request: editing another entry of the group keeps this key
+    fn query_key(&self) -> String {
+        api_url(&self.http.base_url, "connections", &query_pairs(&self.params, &self.own_query))
+    }
+
+    fn shared_request(&self) -> Option<String> {
+        Some(self.url())
+    }
+
+    fn fetch(&self) -> FetchFuture {
+        Box::pin(fetch_connections(self.url(), self.to.clone(), self.limit, self.http.clone()))
+    }
+}
+
+// station=Zurich,Bahnhofplatz&limit=20, filtered by line, category and headsign
+pub struct StationboardProvider {
+    // station and means of transport
+    params: Vec<(String, String)>,
+    // of the request, shared with the entries of the same stop
+    query: MergedQuery,
+    // what the entry asks for itself, the key must not change with the other entries
+    own_query: MergedQuery,
+    filter: StationboardFilter,
+    // departures kept after filtering, the shared request may ask for more
+    limit: u32,
+    http: Http,
+}
+
+#[derive(Clone)]
+struct StationboardFilter {
+    lines: Vec<String>,
+    categories: Vec<String>,
+    // lowercase, matched anywhere in the headsign
+    destinations: Vec<String>,
+}
+
+impl StationboardFilter {
+    fn keeps(&self, departure: &Departure) -> bool {
+        let any_equal = |values: &[String], value: &str| {
+            values.is_empty() || values.iter().any(|v| v.eq_ignore_ascii_case(value))
+        };
+        let headsign = departure.destination.to_lowercase();
+        any_equal(&self.lines, &departure.line)
+            && any_equal(&self.categories, &departure.category)
+            && (self.destinations.is_empty() || self.destinations.iter().any(|d| headsign.contains(d.as_str())))
+    }
+}
+
+impl StationboardProvider {
+    pub fn new(entry: &EntryConfig, http: Http) -> StationboardProvider {
+        StationboardProvider {
+            params: stationboard_params(entry),
+            query: shared_query(ProviderKind::Stationboard, entry, &http),
+            own_query: own_query(ProviderKind::Stationboard, entry),
+            filter: StationboardFilter {
+                lines: entry.lines.clone(),
+                categories: entry.categories.clone(),
+                destinations: entry.destinations.iter().map(|d| d.to_lowercase()).collect(),
+            },
+            limit: entry.limit,
+            http,
+        }
+    }
+
+    fn url(&self) -> String {
+        api_url(&self.http.base_url, "stationboard", &query_pairs(&self.params, &self.query))
+    }
+}
+
+fn stationboard_departure(journey: &StationboardJourney) -> Option<Departure> {
+    let scheduled = UNIX_EPOCH + Duration::from_secs(journey.stop.departure_timestamp?);
+    let (realtime, status) = realtime_of(scheduled, journey.stop.delay);
+    let (platform, platform_changed) = journey.stop.platform();
+    Some(Departure {
+        line: journey.number.clone().unwrap_or_default(),
+        category: journey.category.clone().unwrap_or_default(),
+        destination: journey.to.clone().unwrap_or_default(),
+        scheduled,
+        realtime,
+        platform,
+        platform_changed,
+        status,
+        trip: None,
+        arrival: None,
+        legs: vec![],
+    })
+}
+
+async fn fetch_stationboard(url: String, filter: StationboardFilter, limit: u32, http: Http) -> Result<Answer, FetchError> {
+    let (board, received): (Stationboard, _) = get_json(&url, &http).await?;
+    let departures = board.stationboard.iter()
+        .filter_map(stationboard_departure)
+        .filter(|d| filter.keeps(d))
+        .take(limit as usize)
+        .collect();
+    answer_of(departures, received)
+}
+
+impl TransitProvider for StationboardProvider {
+    // the entry's own query and filters, the same board filtered differently is another entry
+    fn query_key(&self) -> String {
+        let own_url = api_url(&self.http.base_url, "stationboard", &query_pairs(&self.params, &self.own_query));
+        format!("{own_url} lines={:?} categories={:?} destinations={:?}",
+                self.filter.lines, self.filter.categories, self.filter.destinations)
+    }
+
+    fn shared_request(&self) -> Option<String> {
+        Some(self.url())
+    }
+
+    fn fetch(&self) -> FetchFuture {
+        Box::pin(fetch_stationboard(self.url(), self.filter.clone(), self.limit, self.http.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn api_url_percent_encodes_the_stations() {
+        let pairs = vec![pair("from", "Genève, Gare Cornavin"), pair("to", "A&B+C#D"), pair("via[]", "Bern")];
+        assert_eq!(
+            api_url("http://localhost/v1/", "connections", &pairs),
+            "http://localhost/v1/connections?from=Gen%C3%A8ve%2C+Gare+Cornavin&to=A%26B%2BC%23D&via%5B%5D=Bern"
+        );
+    }
+
+    fn fixture<T: DeserializeOwned>(name: &str) -> T {
+        let path = format!("{}/fixtures/opendata/{name}.json", env!("CARGO_MANIFEST_DIR"));
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn stationboard() -> Vec<Departure> {
+        let board: Stationboard = fixture("stationboard");
+        board.stationboard.iter().filter_map(stationboard_departure).collect()
+    }
+
+    #[test]
+    fn stationboard_is_parsed_and_filtered() {
+        let filter = StationboardFilter {
+            lines: vec!["2".to_string()],
+            categories: vec!["t".to_string()],
+            destinations: vec!["farbhof".to_string()],
+        };
+        let departures: Vec<Departure> = stationboard().into_iter().filter(|d| filter.keeps(d)).collect();
+        assert_eq!(departures.len(), 2);
+        assert!(departures.iter().all(|d| d.name() == "T2" && d.destination == "Zurich, Farbhof"));
+        assert_eq!(departures[0].delay(), Duration::from_secs(60));
+        assert_eq!(departures[1].status, DepartureStatus::OnTime);
+    }
+
+    fn connections() -> Vec<Departure> {
+        let answer: Connections = fixture("connections");
+        answer.connections.iter().filter_map(|c| connection_departure(c, "Zurich, Farbhof")).collect()
+    }
+
+    #[test]
+    fn connections_are_parsed_with_their_legs() {
+        let departures = connections();
+        assert_eq!(departures.len(), 4);
+
+        let first = &departures[0];
+        assert_eq!((first.category.as_str(), first.line.as_str()), ("T", "2"));
+        assert_eq!(first.destination, "Zurich, Farbhof");
+        assert_eq!(first.status, DepartureStatus::OnTime);
+        assert_eq!(first.travel_time(), Some(Duration::from_secs(1260)));
+
+        // a change in Altstetten, the walk between the two stops is not a leg
+        let change = &departures[1];
+        assert_eq!(change.journey_name(), "S12>B31");
+        assert_eq!(change.legs.len(), 2);
+        assert_eq!((change.legs[0].to.as_str(), change.legs[1].from.as_str()), ("Zurich Altstetten", "Zurich, Bahnhof Altstetten"));
+        assert_eq!(change.delay(), Duration::from_secs(60));
+        assert_eq!(change.status, DepartureStatus::Delayed);
+
+        assert_eq!(departures[3].status, DepartureStatus::Scheduled);
+        assert_eq!(departures[3].realtime, None);
+    }
+
+    #[test]
+    fn the_announced_platform_wins() {
+        let departures = connections();
+        assert_eq!((departures[0].platform.as_deref(), departures[0].platform_changed), (Some("D"), false));
+        assert_eq!((departures[1].platform.as_deref(), departures[1].platform_changed), (Some("7"), true));
+
+        let bus = stationboard().into_iter().find(|d| d.line == "31").unwrap();
+        assert_eq!((bus.platform.as_deref(), bus.platform_changed), (Some("F"), true));
+    }
+
+    fn entry(toml: &str) -> EntryConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    // a mock answering in `mode` on a free port, and the Http asking it
+    fn start_mock(mode: &str) -> Http {
+        let fixtures = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/opendata"));
+        let mode = mock_opendata::parse_mode(mode).unwrap();
+        let mock = mock_opendata::Mock::new(fixtures, mode, Duration::from_secs(1));
+        let (address, server) = mock_opendata::bind(SocketAddr::from(([127, 0, 0, 1], 0)), mock).unwrap();
+        tokio::spawn(server);
+        Http {
+            base_url: format!("http://{address}/v1/"),
+            quota: None,
+            capture: None,
+            requests: Arc::new(RequestCoordinator::default()),
+        }
+    }
+
+    fn connections_provider(http: Http) -> ConnectionsProvider {
+        ConnectionsProvider::new(&entry("label = \"T2\"\nfrom = \"Zurich, Bahnhofplatz/HB\"\nto = \"Zurich, Farbhof\"\nlimit = 3"), http)
+    }
+
+    #[tokio::test]
+    async fn mock_ok_answers_the_departures() {
+        let answer = connections_provider(start_mock("ok")).fetch().await.unwrap();
+        assert_eq!(answer.departures.len(), 3);
+        // the mock moves the first departure two minutes ahead
+        assert!(answer.departures[0].scheduled > SystemTime::now());
+        assert!(SystemTime::now().duration_since(answer.last_update).unwrap() < Duration::from_secs(5));
+
+        let http = start_mock("ok");
+        let board = StationboardProvider::new(&entry("label = \"T4\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlines = [\"4\"]"), http);
+        let answer = board.fetch().await.unwrap();
+        assert_eq!(answer.departures.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_shared_stationboard_keeps_the_entry_limit() {
+        let few = entry("label = \"a\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 3");
+        let many = entry("label = \"b\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 30");
+        let mut http = start_mock("ok");
+        http.requests = Arc::new(RequestCoordinator::new(merged_queries([&few, &many].into_iter())));
+        let few = StationboardProvider::new(&few, http.clone()).fetch().await.unwrap();
+        let many = StationboardProvider::new(&many, http).fetch().await.unwrap();
+        assert_eq!((few.departures.len(), many.departures.len()), (3, 8));
+    }
+
+    #[tokio::test]
+    async fn mock_delay_answers_late() {
+        let started = Instant::now();
+        let answer = connections_provider(start_mock("delay")).fetch().await;
+        assert!(answer.is_ok());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn mock_timeout_is_a_network_error() {
+        let answer = connections_provider(start_mock("timeout")).fetch().await;
+        assert_eq!(answer.unwrap_err(), FetchError::Network("timeout".to_string()));
+    }
+
+    #[tokio::test]
+    async fn mock_429_is_rate_limited() {
+        let answer = connections_provider(start_mock("429")).fetch().await;
+        assert_eq!(answer.unwrap_err(), FetchError::RateLimited);
+    }
+
+    #[tokio::test]
+    async fn mock_malformed_is_a_json_error() {
+        let answer = connections_provider(start_mock("malformed")).fetch().await;
+        assert!(matches!(answer, Err(FetchError::Json(_))));
+    }
+
+    #[tokio::test]
+    async fn mock_empty_has_no_departures() {
+        let answer = connections_provider(start_mock("empty")).fetch().await;
+        assert_eq!(answer.unwrap_err(), FetchError::Empty);
+    }
+
+    #[tokio::test]
+    async fn spent_quota_sends_no_request() {
+        let mut http = start_mock("ok");
+        let path = std::env::temp_dir().join(format!("led-display-quota-{}.json", std::process::id()));
+        http.quota = Some(Arc::new(Quota::load(path, 0)));
+        let answer = connections_provider(http).fetch().await;
+        assert_eq!(answer.unwrap_err(), FetchError::RateLimited);
+    }
+
+    #[test]
+    fn entries_of_a_stop_share_one_query() {
+        let board = entry("label = \"a\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 5\nlines = [\"2\"]");
+        let other = entry("label = \"b\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 20\nfields = [\"stationboard/to\"]");
+        let elsewhere = entry("label = \"c\"\nprovider = \"stationboard\"\nfrom = \"Bern\"");
+        let merged = merged_queries([&board, &other, &elsewhere].into_iter());
+        assert_eq!(merged.len(), 2);
+        let query = &merged[&query_group(ProviderKind::Stationboard, &board).unwrap()];
+        assert_eq!(query.limit, 20);
+        assert_eq!(query.fields.len(), STATIONBOARD_FIELDS.len());
+    }
+
+    #[test]
+    fn editing_an_entry_keeps_the_key_of_the_others() {
+        let board = entry("label = \"a\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 5\nlines = [\"2\"]");
+        let trip = entry("label = \"c\"\nfrom = \"Zurich\"\nto = \"Bern\"\nlimit = 2");
+        let keys = |other: &str| {
+            let other = entry(other);
+            let coordinator = RequestCoordinator::new(merged_queries([&board, &trip, &other].into_iter()));
+            let http = Http { base_url: "http://localhost/v1/".to_string(), quota: None, capture: None, requests: Arc::new(coordinator) };
+            let board = StationboardProvider::new(&board, http.clone());
+            let trip = ConnectionsProvider::new(&trip, http);
+            (board.query_key(), trip.query_key(), board.url())
+        };
+        let before = keys("label = \"b\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 10\nfallback = \"connections\"\nto = \"Bern\"");
+        let after = keys("label = \"b\"\nprovider = \"stationboard\"\nfrom = \"Zurich\"\nlimit = 30\nfields = [\"stationboard/to\"]\nfallback = \"connections\"\nto = \"Bern\"");
+        assert_ne!(before.2, after.2);
+        assert_eq!((before.0, before.1), (after.0, after.1));
+    }
+}
@@ -0,0 +1,556 @@
+// GTFS-Realtime feed: TripUpdates and ServiceAlerts read from a file or an
+// http URL, overlaid on the departures of the gtfs timetable.
+//
+// The messages below are the part of gtfs-realtime.proto we use, prost skips
+// the other fields while decoding.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use prost::Message;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::RealtimeConfig;
+use crate::transit::{Departure, DepartureStatus, TripRef};
+
+// TripDescriptor.ScheduleRelationship
+const TRIP_CANCELED: i32 = 3;
+// StopTimeUpdate.ScheduleRelationship
+const STOP_SKIPPED: i32 = 1;
+const STOP_NO_DATA: i32 = 2;
+// Alert.Effect
+const EFFECT_NO_SERVICE: i32 = 1;
+
+// a feed server that accepts the connection and never answers must not hold the refresh
+#[cfg(not(test))]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+#[cfg(test)]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, PartialEq, Message)]
+struct FeedMessage {
+    #[prost(message, repeated, tag = "2")]
+    entity: Vec<FeedEntity>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FeedEntity {
+    #[prost(bool, optional, tag = "2")]
+    is_deleted: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    trip_update: Option<TripUpdate>,
+    #[prost(message, optional, tag = "5")]
+    alert: Option<Alert>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TripUpdate {
+    #[prost(message, optional, tag = "1")]
+    trip: Option<TripDescriptor>,
+    #[prost(message, repeated, tag = "2")]
+    stop_time_update: Vec<StopTimeUpdate>,
+    // seconds, for the stops without a prediction of their own
+    #[prost(int32, optional, tag = "5")]
+    delay: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    trip_id: Option<String>,
+    // YYYYMMDD
+    #[prost(string, optional, tag = "3")]
+    start_date: Option<String>,
+    #[prost(int32, optional, tag = "4")]
+    schedule_relationship: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StopTimeUpdate {
+    #[prost(uint32, optional, tag = "1")]
+    stop_sequence: Option<u32>,
+    #[prost(message, optional, tag = "2")]
+    arrival: Option<StopTimeEvent>,
+    #[prost(message, optional, tag = "3")]
+    departure: Option<StopTimeEvent>,
+    #[prost(string, optional, tag = "4")]
+    stop_id: Option<String>,
+    #[prost(int32, optional, tag = "5")]
+    schedule_relationship: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StopTimeEvent {
+    // seconds, negative when early
+    #[prost(int32, optional, tag = "1")]
+    delay: Option<i32>,
+    // unix time
+    #[prost(int64, optional, tag = "2")]
+    time: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Alert {
+    #[prost(message, repeated, tag = "1")]
+    active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    informed_entity: Vec<EntitySelector>,
+    #[prost(int32, optional, tag = "7")]
+    effect: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeRange {
+    #[prost(uint64, optional, tag = "1")]
+    start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    end: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EntitySelector {
+    #[prost(string, optional, tag = "2")]
+    route_id: Option<String>,
+    #[prost(message, optional, tag = "4")]
+    trip: Option<TripDescriptor>,
+    #[prost(string, optional, tag = "5")]
+    stop_id: Option<String>,
+}
+
+// what the feed says about one stop of a trip
+struct StopPrediction {
+    sequence: Option<u32>,
+    stop_id: Option<String>,
+    time: Option<SystemTime>,
+    delay: Option<i32>,
+    relationship: i32,
+}
+
+struct TripPrediction {
+    start_date: Option<String>,
+    cancelled: bool,
+    delay: Option<i32>,
+    // in stop order
+    stops: Vec<StopPrediction>,
+}
+
+// a NO_SERVICE alert: the matching departures do not run
+struct NoService {
+    trip_id: Option<String>,
+    route_id: Option<String>,
+    stop_id: Option<String>,
+    // unix seconds, always active when empty
+    periods: Vec<(u64, u64)>,
+}
+
+impl NoService {
+    fn matches(&self, trip: &TripRef, scheduled: SystemTime) -> bool {
+        let same = |wanted: &Option<String>, value: &str| wanted.as_deref().is_none_or(|w| w == value);
+        let at = scheduled.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        same(&self.trip_id, &trip.trip_id)
+            && same(&self.route_id, &trip.route_id)
+            && same(&self.stop_id, &trip.stop_id)
+            && (self.periods.is_empty() || self.periods.iter().any(|&(start, end)| start <= at && at < end))
+    }
+}
+
+#[derive(Default)]
+struct RealtimeIndex {
+    // by trip_id, one prediction per service day
+    trips: HashMap<String, Vec<TripPrediction>>,
+    no_service: Vec<NoService>,
+}
+
+fn unix_time(seconds: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+fn predict(departure: &mut Departure, time: SystemTime) {
+    departure.realtime = Some(time);
+    departure.status = if time > departure.scheduled { DepartureStatus::Delayed } else { DepartureStatus::OnTime };
+}
+
+fn predict_delay(departure: &mut Departure, delay: i32) {
+    let shift = Duration::from_secs(delay.unsigned_abs() as u64);
+    let time = if delay >= 0 { departure.scheduled + shift } else { departure.scheduled - shift };
+    predict(departure, time);
+}
+
+impl RealtimeIndex {
+    fn new(feed: FeedMessage) -> RealtimeIndex {
+        let mut index = RealtimeIndex::default();
+        for entity in feed.entity {
+            if entity.is_deleted == Some(true) {
+                continue;
+            }
+            if let Some(update) = entity.trip_update {
+                index.add_trip_update(update);
+            }
+            if let Some(alert) = entity.alert {
+                index.add_alert(alert);
+            }
+        }
+        index
+    }
+
+    fn add_trip_update(&mut self, update: TripUpdate) {
+        let trip = update.trip.unwrap_or_default();
+        // trips given by route and start time only are not in the timetable index
+        let trip_id = match trip.trip_id {
+            Some(e) => { e }
+            None => { return; }
+        };
+        let stops = update.stop_time_update.into_iter().map(|u| {
+            // the arrival stands in for the departure at the last stops
+            let event = u.departure
+                .filter(|e| e.time.is_some() || e.delay.is_some())
+                .or(u.arrival)
+                .unwrap_or_default();
+            StopPrediction {
+                sequence: u.stop_sequence,
+                stop_id: u.stop_id,
+                time: event.time.map(unix_time),
+                delay: event.delay,
+                relationship: u.schedule_relationship.unwrap_or_default(),
+            }
+        }).collect();
+        self.trips.entry(trip_id).or_default().push(TripPrediction {
+            start_date: trip.start_date,
+            cancelled: trip.schedule_relationship == Some(TRIP_CANCELED),
+            delay: update.delay,
+            stops,
+        });
+    }
+
+    fn add_alert(&mut self, alert: Alert) {
+        if alert.effect != Some(EFFECT_NO_SERVICE) {
+            return;
+        }
+        let periods: Vec<(u64, u64)> = alert.active_period.iter()
+            .map(|p| (p.start.unwrap_or(0), p.end.unwrap_or(u64::MAX)))
+            .collect();
+        for entity in alert.informed_entity {
+            let trip_id = entity.trip.and_then(|t| t.trip_id);
+            // agency wide alerts are too broad to cancel every departure
+            if trip_id.is_none() && entity.route_id.is_none() && entity.stop_id.is_none() {
+                continue;
+            }
+            self.no_service.push(NoService {
+                trip_id,
+                route_id: entity.route_id,
+                stop_id: entity.stop_id,
+                periods: periods.clone(),
+            });
+        }
+    }
+
+    fn apply(&self, departure: &mut Departure) {
+        let trip = match &departure.trip {
+            Some(e) => { e.clone() }
+            None => { return; }
+        };
+        // start again from the timetable, a prediction may have been withdrawn
+        departure.realtime = None;
+        departure.status = DepartureStatus::Scheduled;
+        if self.no_service.iter().any(|n| n.matches(&trip, departure.scheduled)) {
+            departure.status = DepartureStatus::Cancelled;
+            return;
+        }
+        let prediction = self.trips.get(&trip.trip_id)
+            .and_then(|list| list.iter().find(|p| p.start_date.as_ref().is_none_or(|d| *d == trip.start_date)));
+        let prediction = match prediction {
+            Some(e) => { e }
+            None => { return; }
+        };
+        if prediction.cancelled {
+            departure.status = DepartureStatus::Cancelled;
+            return;
+        }
+
+        let at_stop = prediction.stops.iter().find(|s| match s.sequence {
+            Some(sequence) => { sequence == trip.stop_sequence }
+            None => { s.stop_id.as_deref() == Some(trip.stop_id.as_str()) }
+        });
+        if let Some(stop) = at_stop {
+            match stop.relationship {
+                STOP_SKIPPED => {
+                    departure.status = DepartureStatus::Cancelled;
+                    return;
+                }
+                STOP_NO_DATA => { return; }
+                _ => {}
+            }
+            if let Some(time) = stop.time {
+                predict(departure, time);
+                return;
+            }
+            if let Some(delay) = stop.delay {
+                predict_delay(departure, delay);
+                return;
+            }
+        }
+
+        // the delay of the last predicted stop before this one carries on, else the trip delay
+        let before = prediction.stops.iter().rev()
+            .filter(|s| s.sequence.is_some_and(|sequence| sequence < trip.stop_sequence))
+            .find(|s| s.delay.is_some() || s.relationship == STOP_NO_DATA);
+        let delay = match before {
+            Some(s) if s.relationship == STOP_NO_DATA => { return; }
+            Some(s) => { s.delay }
+            None => { prediction.delay }
+        };
+        if let Some(delay) = delay {
+            predict_delay(departure, delay);
+        }
+    }
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default()
+    })
+}
+
+async fn read_feed(source: &str) -> Result<FeedMessage, String> {
+    let bytes = if source.starts_with("http://") || source.starts_with("https://") {
+        let response = client().get(source).send().await.map_err(|e| format!("cannot get {source}: {e}"))?;
+        if !response.status().is_success() {
+            return Err(format!("{source} answered {}", response.status()));
+        }
+        response.bytes().await.map_err(|e| format!("cannot read the answer of {source}: {e}"))?.to_vec()
+    } else {
+        tokio::fs::read(source).await.map_err(|e| format!("cannot read {source}: {e}"))?
+    };
+    FeedMessage::decode(bytes.as_slice()).map_err(|e| format!("{source} is not a GTFS-RT feed: {e}"))
+}
+
+// the feed is read in the background, the render loop only looks at the last index
+pub struct RealtimeFeed {
+    source: String,
+    refresh_interval: Duration,
+    index: Arc<RwLock<RealtimeIndex>>,
+    task: JoinHandle<()>,
+}
+
+impl RealtimeFeed {
+    fn start(config: &RealtimeConfig) -> RealtimeFeed {
+        let index = Arc::new(RwLock::new(RealtimeIndex::default()));
+        let source = config.source.clone();
+        let refresh_interval = config.refresh_interval();
+        let shared = index.clone();
+        let task = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(refresh_interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                match read_feed(&source).await {
+                    Ok(feed) => {
+                        let fresh = RealtimeIndex::new(feed);
+                        log!("realtime: {} trip updates, {} closures from {source}", fresh.trips.len(), fresh.no_service.len());
+                        *shared.write().unwrap() = fresh;
+                    }
+                    Err(e) => { log!("realtime: {e}, keeping the previous predictions"); }
+                }
+            }
+        });
+        RealtimeFeed { source: config.source.clone(), refresh_interval, index, task }
+    }
+
+    pub fn apply(&self, departures: &mut [Departure]) {
+        let index = self.index.read().unwrap();
+        for departure in departures {
+            index.apply(departure);
+        }
+    }
+}
+
+impl Drop for RealtimeFeed {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// the feed keeps its predictions across config reloads unless its settings changed
+pub fn start_if_changed(config: &RealtimeConfig, running: &Option<Arc<RealtimeFeed>>) -> Arc<RealtimeFeed> {
+    if let Some(feed) = running {
+        if feed.source == config.source && feed.refresh_interval == config.refresh_interval() {
+            return feed.clone();
+        }
+    }
+    Arc::new(RealtimeFeed::start(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduled() -> SystemTime {
+        unix_time(1_718_172_000)
+    }
+
+    fn departure(trip_id: &str, stop_sequence: u32) -> Departure {
+        Departure {
+            line: "2".to_string(),
+            category: "T".to_string(),
+            destination: "Farbhof".to_string(),
+            scheduled: scheduled(),
+            realtime: None,
+            platform: None,
+            platform_changed: false,
+            status: DepartureStatus::Scheduled,
+            trip: Some(TripRef {
+                trip_id: trip_id.to_string(),
+                route_id: "R2".to_string(),
+                start_date: "20240612".to_string(),
+                stop_id: format!("S{stop_sequence}"),
+                stop_sequence,
+            }),
+            arrival: None,
+            legs: vec![],
+        }
+    }
+
+    fn trip(trip_id: &str) -> TripDescriptor {
+        TripDescriptor { trip_id: Some(trip_id.to_string()), start_date: Some("20240612".to_string()), schedule_relationship: None }
+    }
+
+    fn stop(stop_sequence: u32, delay: Option<i32>, relationship: i32) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_sequence: Some(stop_sequence),
+            departure: delay.map(|delay| StopTimeEvent { delay: Some(delay), time: None }),
+            schedule_relationship: Some(relationship),
+            ..Default::default()
+        }
+    }
+
+    fn update(trip: TripDescriptor, stops: Vec<StopTimeUpdate>, delay: Option<i32>) -> FeedEntity {
+        FeedEntity { trip_update: Some(TripUpdate { trip: Some(trip), stop_time_update: stops, delay }), ..Default::default() }
+    }
+
+    fn no_service(entity: EntitySelector, period: Option<(u64, u64)>) -> FeedEntity {
+        let alert = Alert {
+            active_period: period.iter().map(|&(start, end)| TimeRange { start: Some(start), end: Some(end) }).collect(),
+            informed_entity: vec![entity],
+            effect: Some(EFFECT_NO_SERVICE),
+        };
+        FeedEntity { alert: Some(alert), ..Default::default() }
+    }
+
+    // the departure at `stop_sequence` of trip "t1" once `entities` are applied
+    fn applied(entities: Vec<FeedEntity>, stop_sequence: u32) -> Departure {
+        let index = RealtimeIndex::new(FeedMessage { entity: entities });
+        let mut departure = departure("t1", stop_sequence);
+        index.apply(&mut departure);
+        departure
+    }
+
+    #[test]
+    fn the_prediction_at_the_stop() {
+        let mut at_stop = stop(3, None, 0);
+        at_stop.departure = Some(StopTimeEvent { delay: None, time: Some(1_718_172_120) });
+        let found = applied(vec![update(trip("t1"), vec![at_stop], None)], 3);
+        assert_eq!(found.realtime, Some(scheduled() + Duration::from_secs(120)));
+        assert_eq!(found.status, DepartureStatus::Delayed);
+
+        let early = applied(vec![update(trip("t1"), vec![stop(3, Some(-30), 0)], None)], 3);
+        assert_eq!(early.realtime, Some(scheduled() - Duration::from_secs(30)));
+        assert_eq!(early.status, DepartureStatus::OnTime);
+    }
+
+    #[test]
+    fn an_earlier_delay_carries_on() {
+        let found = applied(vec![update(trip("t1"), vec![stop(1, Some(60), 0), stop(5, Some(300), 0)], Some(10))], 3);
+        assert_eq!(found.delay(), Duration::from_secs(60));
+        // no stop before this one, the delay of the trip
+        let found = applied(vec![update(trip("t1"), vec![stop(5, Some(300), 0)], Some(90))], 3);
+        assert_eq!(found.delay(), Duration::from_secs(90));
+        // the feed does not know past the last stop without data
+        let found = applied(vec![update(trip("t1"), vec![stop(1, Some(60), 0), stop(2, None, STOP_NO_DATA)], None)], 3);
+        assert_eq!((found.realtime, found.status), (None, DepartureStatus::Scheduled));
+    }
+
+    #[test]
+    fn cancelled_trips_and_skipped_stops() {
+        let mut cancelled = trip("t1");
+        cancelled.schedule_relationship = Some(TRIP_CANCELED);
+        assert_eq!(applied(vec![update(cancelled, vec![], None)], 3).status, DepartureStatus::Cancelled);
+        let skipped = update(trip("t1"), vec![stop(3, None, STOP_SKIPPED)], None);
+        assert_eq!(applied(vec![skipped], 3).status, DepartureStatus::Cancelled);
+    }
+
+    #[test]
+    fn other_trips_and_days_are_left_alone() {
+        let mut other_day = trip("t1");
+        other_day.start_date = Some("20240613".to_string());
+        let found = applied(vec![update(other_day, vec![], Some(60)), update(trip("t2"), vec![], Some(60))], 3);
+        assert_eq!((found.realtime, found.status), (None, DepartureStatus::Scheduled));
+    }
+
+    #[test]
+    fn no_service_alerts_cancel_their_departures() {
+        let route = EntitySelector { route_id: Some("R2".to_string()), ..Default::default() };
+        let at = 1_718_172_000;
+        assert_eq!(applied(vec![no_service(route.clone(), None)], 3).status, DepartureStatus::Cancelled);
+        assert_eq!(applied(vec![no_service(route.clone(), Some((at, at + 60)))], 3).status, DepartureStatus::Cancelled);
+        assert_eq!(applied(vec![no_service(route, Some((at + 60, at + 120)))], 3).status, DepartureStatus::Scheduled);
+        let stop = EntitySelector { stop_id: Some("S4".to_string()), ..Default::default() };
+        assert_eq!(applied(vec![no_service(stop, None)], 3).status, DepartureStatus::Scheduled);
+        // agency wide
+        assert_eq!(applied(vec![no_service(EntitySelector::default(), None)], 3).status, DepartureStatus::Scheduled);
+    }
+
+    #[test]
+    fn departures_without_a_trip_keep_their_times() {
+        let index = RealtimeIndex::new(FeedMessage { entity: vec![update(trip("t1"), vec![], Some(60))] });
+        let mut api = departure("t1", 3);
+        api.trip = None;
+        api.realtime = Some(scheduled() + Duration::from_secs(240));
+        api.status = DepartureStatus::Delayed;
+        let before = api.clone();
+        index.apply(&mut api);
+        assert_eq!(api, before);
+
+        // a withdrawn prediction brings the timetable back
+        let mut withdrawn = departure("t2", 3);
+        withdrawn.realtime = before.realtime;
+        index.apply(&mut withdrawn);
+        assert_eq!((withdrawn.realtime, withdrawn.status), (None, DepartureStatus::Scheduled));
+    }
+
+    #[tokio::test]
+    async fn the_feed_is_read_from_a_file() {
+        let path = std::env::temp_dir().join(format!("led-display-{}-trip-updates.pb", std::process::id()));
+        let feed = FeedMessage { entity: vec![update(trip("t1"), vec![stop(3, Some(120), 0)], None)] };
+        std::fs::write(&path, feed.encode_to_vec()).unwrap();
+        let config = RealtimeConfig { source: path.to_string_lossy().to_string(), refresh_interval: 30 };
+        let feed = RealtimeFeed::start(&config);
+        for _ in 0..100 {
+            if !feed.index.read().unwrap().trips.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mut departures = vec![departure("t1", 3), departure("t2", 3)];
+        feed.apply(&mut departures);
+        assert_eq!(departures[0].delay(), Duration::from_secs(120));
+        assert_eq!(departures[1].realtime, None);
+
+        // a reload with the same settings keeps the feed and its predictions
+        let running = Some(Arc::new(feed));
+        assert!(Arc::ptr_eq(&start_if_changed(&config, &running), running.as_ref().unwrap()));
+        let faster = RealtimeConfig { refresh_interval: 10, ..config };
+        assert!(!Arc::ptr_eq(&start_if_changed(&faster, &running), running.as_ref().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn a_silent_feed_server_times_out() {
+        // connections wait in the backlog, nothing ever answers them
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let source = format!("http://{}/feed", listener.local_addr().unwrap());
+        let started = std::time::Instant::now();
+        assert!(read_feed(&source).await.unwrap_err().starts_with("cannot get"));
+        assert!(started.elapsed() < REQUEST_TIMEOUT * 2);
+    }
+}
@@ -0,0 +1,71 @@
+// Last answer of each query, saved in the state directory so the board has
+// something to show right after a reboot, before the network is back.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+
+use crate::transit::Answer;
+
+#[derive(Serialize, Deserialize)]
+struct SavedAnswer {
+    // the full query key, the file name is only its hash
+    query: String,
+    answer: Answer,
+}
+
+pub struct AnswerStore {
+    dir: PathBuf,
+}
+
+// FNV-1a, stable across builds unlike the std hasher
+pub fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn file_name(query: &str) -> String {
+    format!("{:016x}.json", fnv1a(query))
+}
+
+impl AnswerStore {
+    pub fn new(dir: PathBuf) -> AnswerStore {
+        AnswerStore { dir }
+    }
+
+    // the saved departures of `query` still to come, None when there are none
+    pub fn load(&self, query: &str, now: SystemTime) -> Option<Answer> {
+        let text = fs::read_to_string(self.dir.join(file_name(query))).ok()?;
+        let saved: SavedAnswer = match serde_json::from_str(&text) {
+            Ok(e) => { e }
+            Err(e) => {
+                log!("answers: cannot read the saved answer of {query}: {e}");
+                return None;
+            }
+        };
+        if saved.query != query {
+            return None;
+        }
+        let mut answer = saved.answer;
+        answer.departures.retain(|d| d.time() > now);
+        if answer.departures.is_empty() {
+            return None;
+        }
+        Some(answer)
+    }
+
+    pub fn save(&self, query: &str, answer: &Answer) {
+        let saved = SavedAnswer { query: query.to_string(), answer: answer.clone() };
+        let written = fs::create_dir_all(&self.dir).map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string(&saved).map_err(|e| e.to_string()))
+            .and_then(|text| fs::write(self.dir.join(file_name(query)), text).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log!("answers: cannot save the answer of {query}: {e}");
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// Remote control over HTTP: serves the phone remote of html/ and turns its
+// `?name=...` requests into WebEvents for the render loop. GET /status
+// returns what the board is showing, the API quota and the last fetch
+// of each entry as JSON.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use tokio::sync::{mpsc, watch};
+
+use crate::config::WebConfig;
+use crate::quota::QuotaUsage;
+use crate::WebEvent;
+
+// the remote fetches `getIP() + "?name=..."`, a relative URL works from any address
+const IP_JS: &str = "function getIP() {\n    return \"/\"\n}\n";
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StatusReport {
+    pub brightness: u8,
+    pub playing: bool,
+    pub page: usize,
+    pub entry: String,
+    // opendata.ch requests of the current UTC day
+    pub quota: Option<QuotaUsage>,
+    pub entries: Vec<EntryStatus>,
+}
+
+// freshness of the departures of one entry
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct EntryStatus {
+    pub label: String,
+    // unix seconds of the last fetch, None before the first answer
+    pub last_update: Option<u64>,
+    // why the last fetch failed
+    pub error: Option<String>,
+}
+
+struct ServerState {
+    assets: PathBuf,
+    events: mpsc::UnboundedSender<WebEvent>,
+    status: watch::Receiver<StatusReport>,
+}
+
+fn event_from_name(name: &str) -> Option<WebEvent> {
+    match name {
+        "lum_up" => Some(WebEvent::BrightnessUp),
+        "lum_down" => Some(WebEvent::BrightnessDown),
+        "dir_next" => Some(WebEvent::NextDest),
+        "dir_previous" => Some(WebEvent::PrevDest),
+        "reset" => Some(WebEvent::Reset),
+        "toggle_play" => Some(WebEvent::TogglePlay),
+        _ => None,
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+fn reply(status: StatusCode, content_type: &str, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    if let Ok(value) = content_type.parse() {
+        response.headers_mut().insert(hyper::header::CONTENT_TYPE, value);
+    }
+    response
+}
+
+fn text(status: StatusCode, message: &str) -> Response<Body> {
+    reply(status, "text/plain; charset=utf-8", message.to_string())
+}
+
+async fn serve_asset(assets: &Path, request_path: &str) -> Response<Body> {
+    let relative = match request_path.trim_start_matches('/') {
+        "" => { "index.html" }
+        e => { e }
+    };
+    let relative = Path::new(relative);
+    // only plain names below the assets directory
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return text(StatusCode::NOT_FOUND, "not found");
+    }
+    let path = assets.join(relative);
+    match tokio::fs::read(&path).await {
+        Ok(content) => { reply(StatusCode::OK, content_type(&path), content) }
+        Err(_) => { text(StatusCode::NOT_FOUND, "not found") }
+    }
+}
+
+async fn handle(state: &ServerState, request: Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+    }
+
+    let name = request.uri().query().and_then(|query| {
+        query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "name")
+            .map(|(_, value)| value.to_string())
+    });
+    if let Some(name) = name {
+        return match event_from_name(&name) {
+            Some(event) => {
+                if state.events.send(event).is_err() {
+                    return text(StatusCode::SERVICE_UNAVAILABLE, "the display is stopping");
+                }
+                text(StatusCode::OK, "ok")
+            }
+            None => { text(StatusCode::BAD_REQUEST, &format!("unknown command {name}")) }
+        };
+    }
+
+    match request.uri().path() {
+        "/ip.js" => { reply(StatusCode::OK, "text/javascript; charset=utf-8", IP_JS) }
+        "/status" => {
+            let status = state.status.borrow().clone();
+            match serde_json::to_string(&status) {
+                Ok(json) => { reply(StatusCode::OK, "application/json", json) }
+                Err(e) => { text(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()) }
+            }
+        }
+        path => { serve_asset(&state.assets, path).await }
+    }
+}
+
+// binds the address and serves in the background, the events go to the render loop
+pub fn start_server(config: &WebConfig, status: watch::Receiver<StatusReport>)
+                    -> Result<mpsc::UnboundedReceiver<WebEvent>, String> {
+    let address: SocketAddr = config.address.parse()
+        .map_err(|e| format!("web.address: {e}"))?;
+    let (events, receiver) = mpsc::unbounded_channel();
+    let state = Arc::new(ServerState {
+        assets: PathBuf::from(&config.assets),
+        events,
+        status,
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&address)
+        .map_err(|e| format!("cannot listen on {address}: {e}"))?
+        .serve(make_service);
+    log!("remote control on http://{address}/");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log!("remote control server stopped: {e}");
+        }
+    });
+    Ok(receiver)
+}
@@ -1,4 +1 @@
-
-
-
-#include "../3rdparty/rpi-rgb-led-matrix/include/led-matrix-c.h"
+#include "led-matrix-c.h"